@@ -4,21 +4,28 @@
 
 #include "../fmi-standard/headers/fmi2FunctionTypes.h"
 
+typedef void (*log_function)(fmi2ComponentEnvironment componentEnvironment,
+                             fmi2String instanceName, fmi2Status status,
+                             fmi2String category, fmi2String message);
+
 extern void callback_log(fmi2ComponentEnvironment componentEnvironment,
                          fmi2String instanceName, fmi2Status status,
                          fmi2String category, fmi2String message);
 
-void callback_logger_handler(fmi2ComponentEnvironment componentEnvironment,
-                             fmi2String instanceName, fmi2Status status,
-                             fmi2String category, fmi2String message, ...)
-{
-    // printf("logger enterted");
+extern void callback_log_environment(fmi2ComponentEnvironment componentEnvironment,
+                                     fmi2String instanceName, fmi2Status status,
+                                     fmi2String category, fmi2String message);
 
-    va_list args;
+static void format_and_log(log_function log,
+                           fmi2ComponentEnvironment componentEnvironment,
+                           fmi2String instanceName, fmi2Status status,
+                           fmi2String category, fmi2String message, va_list args)
+{
+    va_list args_copy;
 
-    va_start(args, message);
-    int buffer_size = vsnprintf(NULL, 0, message, args);
-    va_end(args);
+    va_copy(args_copy, args);
+    int buffer_size = vsnprintf(NULL, 0, message, args_copy);
+    va_end(args_copy);
     if (buffer_size > 0)
     {
         // vsnprintf return value doesn't include the terminating null-byte
@@ -26,13 +33,35 @@ void callback_logger_handler(fmi2ComponentEnvironment componentEnvironment,
 
         if (buffer)
         {
-            va_start(args, message);
             vsprintf(buffer, message, args);
-            va_end(args);
 
-            callback_log(componentEnvironment, instanceName, status, category, buffer);
+            log(componentEnvironment, instanceName, status, category, buffer);
 
             free(buffer);
         }
     }
 }
+
+void callback_logger_handler(fmi2ComponentEnvironment componentEnvironment,
+                             fmi2String instanceName, fmi2Status status,
+                             fmi2String category, fmi2String message, ...)
+{
+    va_list args;
+
+    va_start(args, message);
+    format_and_log(callback_log, componentEnvironment, instanceName, status,
+                   category, message, args);
+    va_end(args);
+}
+
+void callback_environment_logger_handler(fmi2ComponentEnvironment componentEnvironment,
+                                         fmi2String instanceName, fmi2Status status,
+                                         fmi2String category, fmi2String message, ...)
+{
+    va_list args;
+
+    va_start(args, message);
+    format_and_log(callback_log_environment, componentEnvironment, instanceName,
+                   status, category, message, args);
+    va_end(args);
+}
@@ -1,6 +1,46 @@
 use crate as fmi;
 
-/// This function gets called from logger.c
+/// A log callback which receives the formatted message of the FMU.
+pub type LogCallback = unsafe fn(
+    component_environment: fmi::fmi2ComponentEnvironment,
+    instance_name: &str,
+    status: fmi::fmi2Status,
+    category: &str,
+    message: &str,
+);
+
+/// The header of the struct that `componentEnvironment` points to when the
+/// instance logs through [`callback_environment_logger_handler`].
+///
+/// The `componentEnvironment` passed to `fmi2Instantiate()` together with that
+/// handler must be null or point to a `#[repr(C)]` struct that starts with a
+/// `LogEnvironment`. Log messages of that instance are then passed to its `log`
+/// callback instead of the [`log`] crate.
+#[repr(C)]
+pub struct LogEnvironment {
+    pub log: LogCallback,
+}
+
+/// Convert the arguments passed from logger.c.
+///
+/// # Safety
+/// The strings must be null or valid C strings.
+unsafe fn message_parts<'a>(
+    instance_name: fmi::fmi2String,
+    category: fmi::fmi2String,
+    message: fmi::fmi2String,
+) -> (&'a str, &'a str, &'a str) {
+    let to_str = |s: fmi::fmi2String| {
+        if s.is_null() {
+            "NULL"
+        } else {
+            std::ffi::CStr::from_ptr(s).to_str().unwrap_or("NULL")
+        }
+    };
+    (to_str(instance_name), to_str(category), to_str(message))
+}
+
+/// This function gets called from logger.c by [`callback_logger_handler`]
 #[no_mangle]
 extern "C" fn callback_log(
     _component_environment: fmi::fmi2ComponentEnvironment,
@@ -9,33 +49,58 @@ extern "C" fn callback_log(
     category: fmi::fmi2String,
     message: fmi::fmi2String,
 ) {
-    let instance_name = unsafe { std::ffi::CStr::from_ptr(instance_name) }
-        .to_str()
-        .unwrap_or("NULL");
+    let (instance_name, category, message) =
+        unsafe { message_parts(instance_name, category, message) };
+    log_message(instance_name, status, category, message);
+}
+
+/// This function gets called from logger.c by
+/// [`callback_environment_logger_handler`]
+#[no_mangle]
+extern "C" fn callback_log_environment(
+    component_environment: fmi::fmi2ComponentEnvironment,
+    instance_name: fmi::fmi2String,
+    status: fmi::fmi2Status,
+    category: fmi::fmi2String,
+    message: fmi::fmi2String,
+) {
+    let (instance_name, category, message) =
+        unsafe { message_parts(instance_name, category, message) };
+
+    if component_environment.is_null() {
+        log_message(instance_name, status, category, message);
+    } else {
+        unsafe {
+            let environment = &*(component_environment as *const LogEnvironment);
+            (environment.log)(
+                component_environment,
+                instance_name,
+                status,
+                category,
+                message,
+            );
+        }
+    }
+}
+
+/// Log a message of an FMU with the [`log`] crate.
+///
+/// The instance name is the target of the record, and the category is prepended
+/// to the message.
+pub fn log_message(instance_name: &str, status: fmi::fmi2Status, category: &str, message: &str) {
     let level = match status {
         fmi::fmi2Status::fmi2OK => log::Level::Info,
         fmi::fmi2Status::fmi2Warning => log::Level::Warn,
-        fmi::fmi2Status::fmi2Discard => log::Level::Trace,
+        fmi::fmi2Status::fmi2Discard => log::Level::Warn,
         fmi::fmi2Status::fmi2Error => log::Level::Error,
         fmi::fmi2Status::fmi2Fatal => log::Level::Error,
         fmi::fmi2Status::fmi2Pending => log::Level::Info,
     };
 
-    let _category = unsafe { std::ffi::CStr::from_ptr(category) }
-        .to_str()
-        .unwrap_or("NULL");
-
-    let message = unsafe { std::ffi::CStr::from_ptr(message) }
-        .to_str()
-        .unwrap_or("NULL");
-
     log::logger().log(
         &log::Record::builder()
-            .args(format_args!("{}", message.trim_end()))
+            .args(format_args!("[{}] {}", category, message.trim_end()))
             .level(level)
-            .module_path(Some("logger"))
-            .file(Some("logger.rs"))
-            .line(Some(0))
             .target(instance_name)
             .build(),
     );
@@ -54,4 +119,18 @@ extern "C" {
         message: fmi::fmi2String,
         ...
     );
+
+    /// Like [`callback_logger_handler`], but passes the log messages to the
+    /// [`LogEnvironment`] that the `componentEnvironment` starts with.
+    ///
+    /// Only use it for instances whose `componentEnvironment` is null or points to
+    /// a `#[repr(C)]` struct that starts with a [`LogEnvironment`].
+    pub fn callback_environment_logger_handler(
+        componentEnvironment: fmi::fmi2ComponentEnvironment,
+        instanceName: fmi::fmi2String,
+        status: fmi::fmi2Status,
+        category: fmi::fmi2String,
+        message: fmi::fmi2String,
+        ...
+    );
 }
@@ -0,0 +1,428 @@
+//! Command line tool for FMU's.
+//!
+//! `fmu-runner check <file.fmu>` checks an FMU for compliance with the FMI 2.0
+//! standard, similar to the FMU Compliance Checker. It validates the archive
+//! layout and model description, loads the binary of every declared simulation
+//! type for the current platform and checks the symbols it exports, and then runs
+//! a smoke simulation from the start values over the `DefaultExperiment`. The
+//! report is written to stdout, and the exit code is non-zero if any check fails.
+
+use fmu_runner::{
+    fmi2Type,
+    model_description::{
+        Causality, DefaultExperiment, FmiModelDescription, ScalarVariable, SignalType,
+    },
+    solver, Fmu, FmuInstance, FmuLibrary, StartValues, StatusAction, StatusPolicy, StepOutcome,
+};
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage: fmu-runner check <file.fmu>";
+
+/// Number of communication steps of the Co-Simulation smoke simulation when the
+/// `DefaultExperiment` has no `stepSize`.
+const DEFAULT_STEPS: u32 = 500;
+
+/// Stop time of the smoke simulation when the `DefaultExperiment` has none.
+const DEFAULT_DURATION: f64 = 1.0;
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let path = match args.as_slice() {
+        [command, path] if command == "check" => PathBuf::from(path),
+        [help] if help == "-h" || help == "--help" => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut report = Report::default();
+    check(&path, &mut report);
+
+    println!("Checked {}", path.display());
+    print!("{report}");
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// The outcome of every check, in the order they ran.
+#[derive(Default)]
+struct Report {
+    checks: Vec<(String, Vec<String>)>,
+}
+
+impl Report {
+    /// Record a check, which passed if it found no `problems`.
+    fn add(&mut self, name: impl Into<String>, problems: Vec<String>) {
+        self.checks.push((name.into(), problems));
+    }
+
+    /// Record a check that stops at its first problem.
+    fn add_result(&mut self, name: impl Into<String>, result: Result<(), String>) {
+        self.add(name, result.err().into_iter().collect());
+    }
+
+    fn passed(&self) -> bool {
+        self.checks.iter().all(|(_, problems)| problems.is_empty())
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, problems) in &self.checks {
+            let result = if problems.is_empty() { "PASS" } else { "FAIL" };
+            writeln!(f, "[{result}] {name}")?;
+            for problem in problems {
+                writeln!(f, "       {problem}")?;
+            }
+        }
+        writeln!(f, "Result: {}", if self.passed() { "PASS" } else { "FAIL" })
+    }
+}
+
+/// Run all checks on the FMU at `path`. A check that fails prevents the checks
+/// that depend on it.
+fn check(path: &Path, report: &mut Report) {
+    let layout = check_archive(path);
+    let layout_ok = layout.is_ok();
+    report.add_result("Archive layout", layout);
+    if !layout_ok {
+        return;
+    }
+
+    let fmu = match Fmu::unpack(path) {
+        Ok(fmu) => fmu,
+        Err(e) => {
+            report.add_result("Model description", Err(error_chain(&e)));
+            return;
+        }
+    };
+    let model_description = &fmu.model_description;
+    let mut problems = model_description
+        .validate()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if simulation_types(model_description).is_empty() {
+        problems.push("Declares neither ModelExchange nor CoSimulation".to_string());
+    }
+    report.add("Model description", problems);
+
+    for simulation_type in simulation_types(model_description) {
+        let name = match simulation_type {
+            fmi2Type::fmi2ModelExchange => "ModelExchange",
+            fmi2Type::fmi2CoSimulation => "CoSimulation",
+        };
+
+        let lib = match load(path, simulation_type, report, name) {
+            Some(lib) => lib,
+            None => continue,
+        };
+
+        report.add_result(
+            format!("{name} smoke simulation"),
+            match simulation_type {
+                fmi2Type::fmi2ModelExchange => simulate_model_exchange(&lib),
+                fmi2Type::fmi2CoSimulation => simulate_co_simulation(&lib),
+            },
+        );
+    }
+}
+
+/// Check that the file is a zip archive with a `modelDescription.xml` at its root.
+fn check_archive(path: &Path) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| error_chain(&e))?;
+    let archive = zip::ZipArchive::new(file).map_err(|e| error_chain(&e))?;
+    if let Some(name) = archive
+        .file_names()
+        .find(|name| name.starts_with('/') || name.split('/').any(|part| part == ".."))
+    {
+        return Err(format!("Entry {name:?} is outside the archive root"));
+    }
+    if !archive
+        .file_names()
+        .any(|name| name == "modelDescription.xml")
+    {
+        return Err("modelDescription.xml is missing".to_string());
+    }
+    Ok(())
+}
+
+fn simulation_types(model_description: &FmiModelDescription) -> Vec<fmi2Type> {
+    let mut types = Vec::new();
+    if model_description.model_exchange.is_some() {
+        types.push(fmi2Type::fmi2ModelExchange);
+    }
+    if model_description.co_simulation.is_some() {
+        types.push(fmi2Type::fmi2CoSimulation);
+    }
+    types
+}
+
+/// Check that the binary for `simulation_type` exists and exports the functions
+/// of the declared capabilities, and load it.
+///
+/// [`Fmu::load()`] consumes the FMU, so every simulation type unpacks its own copy.
+fn load(
+    path: &Path,
+    simulation_type: fmi2Type,
+    report: &mut Report,
+    name: &str,
+) -> Option<FmuLibrary> {
+    let binary = format!("{name} binary");
+    let fmu = match Fmu::unpack(path) {
+        Ok(fmu) => fmu,
+        Err(e) => {
+            report.add_result(binary, Err(error_chain(&e)));
+            return None;
+        }
+    };
+
+    match fmu.library_path(simulation_type) {
+        Ok(lib_path) if !lib_path.exists() => {
+            // Relative to the archive root rather than the tempdir.
+            let relative = lib_path.iter().rev().take(3).collect::<Vec<_>>();
+            let relative = relative.iter().rev().collect::<PathBuf>();
+            report.add_result(binary, Err(format!("{} is missing", relative.display())));
+            return None;
+        }
+        Err(e) => {
+            report.add_result(binary, Err(error_chain(&e)));
+            return None;
+        }
+        Ok(_) => {}
+    }
+
+    let lib = match fmu.load(simulation_type) {
+        Ok(lib) => lib,
+        Err(e) => {
+            report.add_result(binary, Err(error_chain(&e)));
+            return None;
+        }
+    };
+
+    let capabilities = lib.capabilities();
+    let mut missing = capabilities
+        .missing_required
+        .iter()
+        .map(|function| format!("Missing symbol {function}"))
+        .collect::<Vec<_>>();
+    for capability in capabilities.optional.iter().filter(|c| c.declared) {
+        missing.extend(
+            capability
+                .missing
+                .iter()
+                .map(|function| format!("Missing symbol {function} of {}", capability.attribute)),
+        );
+    }
+    let ok = missing.is_empty();
+    report.add(binary, missing);
+    ok.then_some(lib)
+}
+
+/// The start time, stop time and tolerance of the smoke simulation.
+fn experiment(model_description: &FmiModelDescription) -> (f64, f64, Option<f64>) {
+    match &model_description.default_experiment {
+        Some(experiment) => {
+            let stop_time = if experiment.stop_time > experiment.start_time
+                && experiment.stop_time.is_finite()
+            {
+                experiment.stop_time
+            } else {
+                experiment.start_time + DEFAULT_DURATION
+            };
+            let tolerance = (experiment.tolerance > 0.0).then_some(experiment.tolerance);
+            (experiment.start_time, stop_time, tolerance)
+        }
+        None => (0.0, DEFAULT_DURATION, None),
+    }
+}
+
+/// The communication step size of the smoke simulation. A `stepSize` that isn't
+/// positive and finite would never reach the stop time, and is replaced by a
+/// [`DEFAULT_STEPS`]th of the experiment.
+fn step_size(experiment: Option<&DefaultExperiment>, start_time: f64, stop_time: f64) -> f64 {
+    experiment
+        .and_then(|experiment| experiment.step_size)
+        .filter(|step_size| *step_size > 0.0 && step_size.is_finite())
+        .unwrap_or((stop_time - start_time) / DEFAULT_STEPS as f64)
+}
+
+fn instantiate(lib: &FmuLibrary) -> Result<FmuInstance<&FmuLibrary>, String> {
+    let instance = FmuInstance::instantiate(lib, false).map_err(|e| error_chain(&e))?;
+    // Warnings are allowed, but the smoke simulation should not be discarded.
+    instance.set_status_policy(StatusPolicy {
+        warning: StatusAction::Accept,
+        ..Default::default()
+    });
+    Ok(instance)
+}
+
+/// The `Real` outputs, which are checked to be finite during the simulation.
+fn real_outputs(lib: &FmuLibrary) -> Vec<&ScalarVariable> {
+    lib.variables()
+        .iter()
+        .filter(|v| {
+            v.causality == Causality::Output && matches!(v.signal_type, SignalType::Real(_))
+        })
+        .collect()
+}
+
+fn check_outputs<C: std::borrow::Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    outputs: &[&ScalarVariable],
+    time: f64,
+) -> Result<(), String> {
+    let values = instance.get_reals(outputs).map_err(|e| error_chain(&e))?;
+    for output in outputs {
+        if !values[output].is_finite() {
+            return Err(format!(
+                "Output {} is {} at t = {time}",
+                output.name, values[output]
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn simulate_co_simulation(lib: &FmuLibrary) -> Result<(), String> {
+    let (start_time, stop_time, tolerance) = experiment(&lib.model_description);
+    let step_size = step_size(
+        lib.model_description.default_experiment.as_ref(),
+        start_time,
+        stop_time,
+    );
+    let outputs = real_outputs(lib);
+
+    let instance = instantiate(lib)?;
+    let fmu_error = |e: fmu_runner::FmuError| error_chain(&e);
+    instance
+        .setup_experiment(start_time, Some(stop_time), tolerance)
+        .map_err(fmu_error)?;
+    instance
+        .apply_start_values(&StartValues::new())
+        .map_err(fmu_error)?;
+    instance.enter_initialization_mode().map_err(fmu_error)?;
+    instance.exit_initialization_mode().map_err(fmu_error)?;
+    check_outputs(&instance, &outputs, start_time)?;
+
+    let mut time = start_time;
+    while time < stop_time - step_size * 1e-6 {
+        let h = step_size.min(stop_time - time);
+        match instance.do_step(time, h, true).map_err(fmu_error)? {
+            StepOutcome::Completed { .. } => {}
+            StepOutcome::Terminated { .. } => break,
+            StepOutcome::Discarded {
+                last_successful_time,
+            } => {
+                return Err(format!(
+                    "fmi2DoStep() was discarded at t = {last_successful_time}"
+                ))
+            }
+        }
+        time += h;
+        check_outputs(&instance, &outputs, time)?;
+    }
+
+    instance.terminate().map_err(fmu_error)?;
+    Ok(())
+}
+
+fn simulate_model_exchange(lib: &FmuLibrary) -> Result<(), String> {
+    let (start_time, stop_time, tolerance) = experiment(&lib.model_description);
+    let outputs = real_outputs(lib);
+
+    let instance = instantiate(lib)?;
+    let fmu_error = |e: fmu_runner::FmuError| error_chain(&e);
+    instance
+        .setup_experiment(start_time, Some(stop_time), tolerance)
+        .map_err(fmu_error)?;
+    instance
+        .apply_start_values(&StartValues::new())
+        .map_err(fmu_error)?;
+    instance.enter_initialization_mode().map_err(fmu_error)?;
+    instance.exit_initialization_mode().map_err(fmu_error)?;
+
+    let mut integrator = solver::Dopri45::from_model_description(&lib.model_description);
+    let mut output_error = Ok(());
+    solver::simulate(
+        &instance,
+        &mut integrator,
+        start_time,
+        stop_time,
+        |time, instance| {
+            if output_error.is_ok() {
+                output_error = check_outputs(instance, &outputs, time);
+            }
+        },
+    )
+    .map_err(|e| error_chain(&e))?;
+    output_error?;
+
+    instance.terminate().map_err(fmu_error)?;
+    Ok(())
+}
+
+/// Format an error followed by its sources, since the errors of this crate keep
+/// the details in their source.
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+    message
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::default();
+        report.add("Archive layout", vec![]);
+        assert!(report.passed());
+        report.add_result(
+            "Model description",
+            Err("fmiVersion is \"3.0\"".to_string()),
+        );
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "[PASS] Archive layout\n[FAIL] Model description\n       fmiVersion is \"3.0\"\nResult: FAIL\n"
+        );
+    }
+
+    #[test]
+    fn test_step_size() {
+        let mut experiment = DefaultExperiment {
+            step_size: Some(0.01),
+            ..Default::default()
+        };
+        assert_eq!(step_size(Some(&experiment), 0.0, 1.0), 0.01);
+        assert_eq!(step_size(None, 0.0, 1.0), 1.0 / DEFAULT_STEPS as f64);
+
+        for invalid in [0.0, -0.01, f64::NAN, f64::INFINITY] {
+            experiment.step_size = Some(invalid);
+            assert_eq!(
+                step_size(Some(&experiment), 0.0, 1.0),
+                1.0 / DEFAULT_STEPS as f64
+            );
+        }
+    }
+}
@@ -1,14 +1,20 @@
-use crate::model_description::{FmiModelDescription, ScalarVariable};
+use crate::model_description::{
+    Causality, FmiModelDescription, ScalarVariable, ScalarVariables, Variability,
+};
+use environment::ComponentEnvironment;
 use itertools::Itertools;
 use libfmi::{
-    fmi2Boolean, fmi2Byte, fmi2CallbackFunctions, fmi2Component, fmi2FMUstate, fmi2Integer,
-    fmi2Real, fmi2Status, fmi2Type, fmi2ValueReference, Fmi2Dll,
+    fmi2Boolean, fmi2Byte, fmi2CallbackFunctions, fmi2Component, fmi2EventInfo, fmi2FMUstate,
+    fmi2Integer, fmi2Real, fmi2Status, fmi2StatusKind, fmi2String, fmi2Type, fmi2ValueReference,
+    Fmi2Dll,
 };
+use status::worst_status;
 use std::{
     borrow::Borrow,
+    cell::Cell,
     collections::HashMap,
     env,
-    ffi::CString,
+    ffi::{CStr, CString, NulError},
     fmt::Display,
     fs, io,
     iter::zip,
@@ -20,6 +26,20 @@ use std::{
 use thiserror::Error;
 use zip::result::ZipError;
 
+mod capabilities;
+mod environment;
+mod logging;
+mod start_values;
+mod state;
+mod status;
+
+pub use capabilities::{Capabilities, Capability};
+pub use environment::StepHandle;
+pub use logging::{FmuLogRecord, LogCrateHandler, LogHandler};
+pub use start_values::{StartValue, StartValues};
+pub use state::InstanceState;
+pub use status::{Checked, StatusAction, StatusPolicy};
+
 /// A unpacked FMU with a parsed model description.
 #[derive(Debug)]
 pub struct Fmu {
@@ -46,6 +66,8 @@ pub struct FmuLibrary {
     pub fmu: Fmu,
     /// Generates unique instance names for starting new FMU instances.
     instance_name_factory: InstanceNameFactory,
+    /// The FMI functions the dll doesn't export.
+    missing_functions: Vec<&'static str>,
 }
 
 /// A simulation "instance", ready to execute.
@@ -62,12 +84,23 @@ pub struct FmuInstance<C: Borrow<FmuLibrary>> {
     instance: *mut os::raw::c_void,
     #[allow(dead_code)]
     callbacks: Box<fmi2CallbackFunctions>,
+    /// State shared with the FMU's callbacks.
+    environment: Box<ComponentEnvironment>,
+    /// The name passed to `fmi2Instantiate()`.
+    instance_name: String,
+    /// The state of the instance in the FMI state machine.
+    state: Cell<InstanceState>,
+    status_policy: Cell<StatusPolicy>,
 }
 
-pub struct FmuState<'fmu, C: Borrow<FmuLibrary>>(fmi2FMUstate, &'fmu FmuInstance<C>);
+/// A snapshot of an instance, including the [`InstanceState`] it was in.
+pub struct FmuState<'fmu, C: Borrow<FmuLibrary>>(fmi2FMUstate, &'fmu FmuInstance<C>, InstanceState);
 
 impl<'fmu, C: Borrow<FmuLibrary>> Drop for FmuState<'fmu, C> {
     fn drop(&mut self) {
+        if !self.1.lib.borrow().exports("fmi2FreeFMUstate") {
+            return;
+        }
         unsafe {
             self.1
                 .lib
@@ -81,39 +114,50 @@ impl<'fmu, C: Borrow<FmuLibrary>> Drop for FmuState<'fmu, C> {
 pub struct FmuGetSetStateCapability<'fmu, C: Borrow<FmuLibrary>>(&'fmu FmuInstance<C>);
 
 impl<'fmu, C: Borrow<FmuLibrary>> FmuGetSetStateCapability<'fmu, C> {
-    pub fn get_state(&self) -> Result<FmuState<'fmu, C>, FmuError> {
+    pub fn get_state(&self) -> Result<Checked<FmuState<'fmu, C>>, FmuError> {
+        self.0
+            .check_state("fmi2GetFMUstate", state::NO_PENDING_STEP)?;
         let mut fmu2state: fmi2FMUstate = std::ptr::null_mut();
         let pfmu2state = std::ptr::addr_of_mut!(fmu2state);
-        FmuInstance::<C>::ok_or_err(unsafe {
+        let status = self.0.ok_or_err("fmi2GetFMUstate", unsafe {
             self.0
                 .lib
                 .borrow()
                 .fmi
                 .fmi2GetFMUstate(self.0.instance, pfmu2state)
         })?;
-        Ok(FmuState(fmu2state, self.0))
+        Ok(Checked {
+            value: FmuState(fmu2state, self.0, self.0.state.get()),
+            status,
+        })
     }
 
-    pub fn set_state(&self, mut state: FmuState<'fmu, C>) -> Result<(), FmuError> {
+    pub fn set_state(&self, mut state: FmuState<'fmu, C>) -> Result<fmi2Status, FmuError> {
+        self.0
+            .check_state("fmi2SetFMUstate", state::NO_PENDING_STEP)?;
         let pfmu2state = std::ptr::addr_of_mut!(state.0);
-        FmuInstance::<C>::ok_or_err(unsafe {
+        let status = self.0.ok_or_err("fmi2SetFMUstate", unsafe {
             self.0
                 .lib
                 .borrow()
                 .fmi
                 .fmi2SetFMUstate(self.0.instance, *pfmu2state)
         })?;
-        Ok(())
+        self.0.state.set(state.2);
+        Ok(status)
     }
 }
 
 pub struct FmuSerializeStateCapability<'fmu, C: Borrow<FmuLibrary>>(&'fmu FmuInstance<C>);
 
 impl<'fmu, C: Borrow<FmuLibrary>> FmuSerializeStateCapability<'fmu, C> {
-    pub fn serialize_state(&self, state: &FmuState<'fmu, C>) -> Result<Vec<u8>, FmuError> {
+    pub fn serialize_state(&self, state: &FmuState<'fmu, C>) -> Result<Checked<Vec<u8>>, FmuError> {
+        self.0
+            .check_state("fmi2SerializedFMUstateSize", state::NO_PENDING_STEP)?;
+        self.0.lib.borrow().check_symbol("fmi2SerializeFMUstate")?;
         let mut size: usize = 0;
         let pfmu2state = std::ptr::addr_of!(state.0);
-        FmuInstance::<C>::ok_or_err(unsafe {
+        let size_status = self.0.ok_or_err("fmi2SerializedFMUstateSize", unsafe {
             self.0.lib.borrow().fmi.fmi2SerializedFMUstateSize(
                 self.0.instance,
                 *pfmu2state,
@@ -122,7 +166,7 @@ impl<'fmu, C: Borrow<FmuLibrary>> FmuSerializeStateCapability<'fmu, C> {
         })?;
         let mut serialized_state = vec![0u8; size];
         let raw_serialized_state: *mut fmi2Byte = serialized_state.as_mut_ptr() as *mut fmi2Byte;
-        FmuInstance::<C>::ok_or_err(unsafe {
+        let status = self.0.ok_or_err("fmi2SerializeFMUstate", unsafe {
             self.0.lib.borrow().fmi.fmi2SerializeFMUstate(
                 self.0.instance,
                 *pfmu2state,
@@ -130,17 +174,22 @@ impl<'fmu, C: Borrow<FmuLibrary>> FmuSerializeStateCapability<'fmu, C> {
                 size,
             )
         })?;
-        Ok(serialized_state)
+        Ok(Checked {
+            value: serialized_state,
+            status: worst_status(size_status, status),
+        })
     }
 
     pub fn deserialize_state(
         &self,
         serialized_state: &[u8],
-    ) -> Result<FmuState<'fmu, C>, FmuError> {
+    ) -> Result<Checked<FmuState<'fmu, C>>, FmuError> {
+        self.0
+            .check_state("fmi2DeSerializeFMUstate", state::NO_PENDING_STEP)?;
         let mut fmu2state: fmi2FMUstate = std::ptr::null_mut();
         let pfmu2state = std::ptr::addr_of_mut!(fmu2state);
         let raw_serialized_state: *const fmi2Byte = serialized_state.as_ptr() as *const fmi2Byte;
-        FmuInstance::<C>::ok_or_err(unsafe {
+        let status = self.0.ok_or_err("fmi2DeSerializeFMUstate", unsafe {
             self.0.lib.borrow().fmi.fmi2DeSerializeFMUstate(
                 self.0.instance,
                 raw_serialized_state,
@@ -148,7 +197,12 @@ impl<'fmu, C: Borrow<FmuLibrary>> FmuSerializeStateCapability<'fmu, C> {
                 pfmu2state,
             )
         })?;
-        Ok(FmuState(fmu2state, self.0))
+        // The state machine state isn't serialized, so assume the instance is in the
+        // same state as when it was serialized.
+        Ok(Checked {
+            value: FmuState(fmu2state, self.0, self.0.state.get()),
+            status,
+        })
     }
 }
 
@@ -262,6 +316,31 @@ impl Fmu {
     where
         F: FnOnce(&::libloading::Library),
     {
+        let lib_path = self.library_path(simulation_type)?;
+        let model_identifier = self.model_identifier(simulation_type)?.to_owned();
+
+        // Load the library
+        let library = unsafe { ::libloading::Library::new(lib_path)? };
+
+        // Let the user map their own symbols in the library
+        handler(&library);
+
+        // Map our signals in the library
+        let fmi = unsafe { Fmi2Dll::from_library(library) }?;
+        let missing_functions = capabilities::missing_functions(&fmi);
+
+        Ok(FmuLibrary {
+            fmi,
+            simulation_type,
+            fmu: self,
+            instance_name_factory: InstanceNameFactory::new(model_identifier),
+            missing_functions,
+        })
+    }
+
+    /// The path of the dynamic library for `simulation_type` and the current
+    /// platform, whether it exists or not.
+    pub fn library_path(&self, simulation_type: fmi2Type) -> Result<PathBuf, FmuLoadError> {
         let (os_type, lib_type) = match env::consts::OS {
             "macos" => ("darwin", "dylib"),
             "linux" => ("linux", "so"),
@@ -277,22 +356,7 @@ impl Fmu {
             _ => "unknown",
         };
 
-        let model_identifier = match simulation_type {
-            fmi2Type::fmi2ModelExchange => self
-                .model_description
-                .model_exchange
-                .as_ref()
-                .ok_or(FmuLoadError::NoModelExchangeModel)?
-                .model_identifier
-                .clone(),
-            fmi2Type::fmi2CoSimulation => self
-                .model_description
-                .co_simulation
-                .as_ref()
-                .ok_or(FmuLoadError::NoCoSimulationModel)?
-                .model_identifier
-                .clone(),
-        };
+        let model_identifier = self.model_identifier(simulation_type)?;
 
         // construct the library folder string
         let lib_str = os_type.to_owned() + arch_type;
@@ -302,27 +366,30 @@ impl Fmu {
             .unpacked_dir
             .join("binaries")
             .join(lib_str)
-            .join(&model_identifier);
+            .join(model_identifier);
         lib_path.set_extension(lib_type);
 
-        // Load the library
-        let library = unsafe { ::libloading::Library::new(lib_path)? };
-
-        // Let the user map their own symbols in the library
-        handler(&library);
-
-        // Map our signals in the library
-        let fmi = unsafe { Fmi2Dll::from_library(library) }?;
+        Ok(lib_path)
+    }
 
-        Ok(FmuLibrary {
-            fmi,
-            simulation_type,
-            fmu: self,
-            instance_name_factory: InstanceNameFactory::new(model_identifier),
-        })
+    fn model_identifier(&self, simulation_type: fmi2Type) -> Result<&str, FmuLoadError> {
+        match simulation_type {
+            fmi2Type::fmi2ModelExchange => self
+                .model_description
+                .model_exchange
+                .as_ref()
+                .map(|me| me.model_identifier.as_str())
+                .ok_or(FmuLoadError::NoModelExchangeModel),
+            fmi2Type::fmi2CoSimulation => self
+                .model_description
+                .co_simulation
+                .as_ref()
+                .map(|cs| cs.model_identifier.as_str())
+                .ok_or(FmuLoadError::NoCoSimulationModel),
+        }
     }
 
-    pub fn variables(&self) -> &HashMap<String, ScalarVariable> {
+    pub fn variables(&self) -> &ScalarVariables {
         &self.model_description.model_variables.scalar_variable
     }
 }
@@ -331,15 +398,47 @@ unsafe impl<C: Borrow<FmuLibrary>> Send for FmuInstance<C> {}
 
 impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
     /// Call `fmi2Instantiate()` on the FMU library to start a new simulation instance.
+    ///
+    /// Log messages of the instance go to the [`log`] crate through the
+    /// [`LogCrateHandler`].
     pub fn instantiate(lib: C, logging_on: bool) -> Result<Self, FmuError> {
+        Self::instantiate_with_log_handler(lib, logging_on, LogCrateHandler)
+    }
+
+    /// Like [`Self::instantiate()`], but pass the log messages of the instance to
+    /// `log_handler` instead.
+    ///
+    /// This can be used to collect the logs of every instance separately when running
+    /// many instances in parallel, e.g. by sending them to a channel.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use fmu_runner::{Fmu, FmuInstance, FmuLogRecord, fmi2Type};
+    /// # use std::{path::Path, sync::mpsc};
+    /// let fmu = Fmu::unpack(Path::new("./tests/fmu/bouncing_ball.fmu"))?
+    ///     .load(fmi2Type::fmi2CoSimulation)?;
+    ///
+    /// let (sender, receiver) = mpsc::channel();
+    /// let fmu_cs = FmuInstance::instantiate_with_log_handler(&fmu, true, move |record: &FmuLogRecord| {
+    ///     let _ = sender.send(record.clone());
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn instantiate_with_log_handler(
+        lib: C,
+        logging_on: bool,
+        log_handler: impl LogHandler + 'static,
+    ) -> Result<Self, FmuError> {
+        lib.borrow().check_symbol("fmi2Instantiate")?;
         let fmu_guid = &lib.borrow().model_description.guid;
 
+        let environment = Box::new(ComponentEnvironment::new(Box::new(log_handler)));
         let callbacks = Box::<fmi2CallbackFunctions>::new(fmi2CallbackFunctions {
-            logger: Some(libfmi::logger::callback_logger_handler),
+            logger: Some(libfmi::logger::callback_environment_logger_handler),
             allocateMemory: Some(libc::calloc),
             freeMemory: Some(libc::free),
-            stepFinished: None,
-            componentEnvironment: std::ptr::null_mut::<std::os::raw::c_void>(),
+            stepFinished: Some(environment::step_finished),
+            componentEnvironment: environment.as_ptr(),
         });
 
         let fmu_guid = CString::new(fmu_guid.as_bytes()).expect("Error building fmu_guid CString");
@@ -381,10 +480,14 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
             lib,
             instance,
             callbacks,
+            environment,
+            instance_name: instance_name.into_string().unwrap(),
+            state: Cell::new(InstanceState::Instantiated),
+            status_policy: Cell::default(),
         })
     }
 
-    pub fn get_set_state_capability(&self) -> Option<FmuGetSetStateCapability<C>> {
+    pub fn get_set_state_capability(&self) -> Option<FmuGetSetStateCapability<'_, C>> {
         if let Some(description) = self.lib.borrow().model_description.co_simulation.as_ref() {
             if description.can_get_and_set_fmustate {
                 Some(FmuGetSetStateCapability(self))
@@ -404,7 +507,7 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
         }
     }
 
-    pub fn serialize_state_capability(&self) -> Option<FmuSerializeStateCapability<C>> {
+    pub fn serialize_state_capability(&self) -> Option<FmuSerializeStateCapability<'_, C>> {
         if let Some(description) = self.lib.borrow().model_description.co_simulation.as_ref() {
             if description.can_serialize_fmustate {
                 Some(FmuSerializeStateCapability(self))
@@ -424,19 +527,35 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
         }
     }
 
-    pub fn get_types_platform(&self) -> &str {
+    /// Call `fmi2GetTypesPlatform()`.
+    pub fn get_types_platform(&self) -> Result<&str, FmuError> {
+        self.lib.borrow().check_symbol("fmi2GetTypesPlatform")?;
         let types_platform =
-            unsafe { std::ffi::CStr::from_ptr(self.lib.borrow().fmi.fmi2GetTypesPlatform()) }
+            unsafe { CStr::from_ptr(self.lib.borrow().fmi.fmi2GetTypesPlatform()) }
                 .to_str()
                 .unwrap();
-        types_platform
+        Ok(types_platform)
     }
 
+    /// Call `fmi2SetDebugLogging()`.
+    ///
+    /// The `log_categories` must be declared in the `LogCategories` of the model
+    /// description. Only messages in these categories are passed to the log handler,
+    /// or all of them if `log_categories` is empty.
     pub fn set_debug_logging(
         &self,
         logging_on: bool,
         log_categories: &[&str],
-    ) -> Result<(), FmuError> {
+    ) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2SetDebugLogging", state::NO_PENDING_STEP)?;
+        let model_description = &self.lib.borrow().model_description;
+        if let Some(category) = log_categories
+            .iter()
+            .find(|c| model_description.log_category(c).is_none())
+        {
+            return Err(FmuError::UnknownLogCategory(category.to_string()));
+        }
+
         let category_cstr = log_categories
             .iter()
             .map(|c| CString::new(*c).unwrap())
@@ -444,14 +563,21 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
 
         let category_ptrs: Vec<_> = category_cstr.iter().map(|c| c.as_ptr()).collect();
 
-        Self::ok_or_err(unsafe {
+        let arguments = format_args!("loggingOn={logging_on}, categories={log_categories:?}");
+        let status = self.ok_or_err_with("fmi2SetDebugLogging", arguments, unsafe {
             self.lib.borrow().fmi.fmi2SetDebugLogging(
                 self.instance,
                 logging_on as fmi2Boolean,
                 category_ptrs.len(),
                 category_ptrs.as_ptr(),
             )
-        })
+        })?;
+
+        self.environment.set_log_categories(
+            (!log_categories.is_empty())
+                .then(|| log_categories.iter().map(|c| c.to_string()).collect()),
+        );
+        Ok(status)
     }
 
     pub fn setup_experiment(
@@ -459,8 +585,11 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
         start_time: f64,
         stop_time: Option<f64>,
         tolerance: Option<f64>,
-    ) -> Result<(), FmuError> {
-        Self::ok_or_err(unsafe {
+    ) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2SetupExperiment", &[InstanceState::Instantiated])?;
+        let arguments =
+            format_args!("startTime={start_time}, stopTime={stop_time:?}, tolerance={tolerance:?}");
+        self.ok_or_err_with("fmi2SetupExperiment", arguments, unsafe {
             self.lib.borrow().fmi.fmi2SetupExperiment(
                 self.instance,
                 tolerance.is_some() as fmi2Boolean,
@@ -472,84 +601,716 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
         })
     }
 
-    pub fn enter_initialization_mode(&self) -> Result<(), FmuError> {
-        Self::ok_or_err(unsafe {
+    pub fn enter_initialization_mode(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state(
+            "fmi2EnterInitializationMode",
+            &[InstanceState::Instantiated],
+        )?;
+        let status = self.ok_or_err("fmi2EnterInitializationMode", unsafe {
             self.lib
                 .borrow()
                 .fmi
                 .fmi2EnterInitializationMode(self.instance)
-        })
+        })?;
+        self.state.set(InstanceState::InitializationMode);
+        Ok(status)
     }
 
-    pub fn exit_initialization_mode(&self) -> Result<(), FmuError> {
-        Self::ok_or_err(unsafe {
+    /// Call `fmi2ExitInitializationMode()`, after which a Model Exchange instance is
+    /// in Event Mode and a Co-Simulation instance can do steps.
+    pub fn exit_initialization_mode(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state(
+            "fmi2ExitInitializationMode",
+            &[InstanceState::InitializationMode],
+        )?;
+        let status = self.ok_or_err("fmi2ExitInitializationMode", unsafe {
             self.lib
                 .borrow()
                 .fmi
                 .fmi2ExitInitializationMode(self.instance)
-        })
+        })?;
+        self.state.set(match self.lib.borrow().simulation_type {
+            fmi2Type::fmi2ModelExchange => InstanceState::EventMode,
+            fmi2Type::fmi2CoSimulation => InstanceState::SlaveInitialized,
+        });
+        Ok(status)
+    }
+
+    /// Call `fmi2Terminate()` to end the simulation.
+    ///
+    /// Dropping an initialized instance terminates it too.
+    pub fn terminate(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2Terminate", state::RUNNING)?;
+        let status = self.ok_or_err("fmi2Terminate", unsafe {
+            self.lib.borrow().fmi.fmi2Terminate(self.instance)
+        })?;
+        self.state.set(InstanceState::Terminated);
+        Ok(status)
+    }
+
+    /// Call `fmi2Reset()` to bring the instance back to the state right after
+    /// `fmi2Instantiate()`, so that it can run a new simulation without being
+    /// instantiated again.
+    pub fn reset(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2Reset", state::NO_PENDING_STEP)?;
+        let status = self.ok_or_err("fmi2Reset", unsafe {
+            self.lib.borrow().fmi.fmi2Reset(self.instance)
+        })?;
+        self.state.set(InstanceState::Instantiated);
+        Ok(status)
     }
 
     pub fn get_reals<'fmu>(
         &'fmu self,
         signals: &[&'fmu ScalarVariable],
-    ) -> Result<HashMap<&ScalarVariable, fmi2Real>, FmuError> {
-        self.get(signals, Fmi2Dll::fmi2GetReal)
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, fmi2Real>>, FmuError> {
+        self.get("fmi2GetReal", signals, Fmi2Dll::fmi2GetReal)
     }
 
+    /// Get the values of integer variables.
+    ///
+    /// `Enumeration` variables are read as integers as well, see
+    /// [`Self::get_enumeration_items()`] to get the names of their items instead.
     pub fn get_integers<'fmu>(
         &'fmu self,
         signals: &[&'fmu ScalarVariable],
-    ) -> Result<HashMap<&ScalarVariable, fmi2Integer>, FmuError> {
-        self.get(signals, Fmi2Dll::fmi2GetInteger)
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, fmi2Integer>>, FmuError> {
+        self.get("fmi2GetInteger", signals, Fmi2Dll::fmi2GetInteger)
     }
 
     pub fn get_booleans<'fmu>(
         &'fmu self,
         signals: &[&'fmu ScalarVariable],
-    ) -> Result<HashMap<&ScalarVariable, fmi2Integer>, FmuError> {
-        self.get(signals, Fmi2Dll::fmi2GetBoolean)
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, fmi2Integer>>, FmuError> {
+        self.get("fmi2GetBoolean", signals, Fmi2Dll::fmi2GetBoolean)
+    }
+
+    /// Get the values of string variables.
+    ///
+    /// The strings are copied out of the FMU, since the pointers it returns are only
+    /// valid until the next FMI call.
+    pub fn get_strings<'fmu>(
+        &'fmu self,
+        signals: &[&'fmu ScalarVariable],
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, String>>, FmuError> {
+        let values = self.get("fmi2GetString", signals, Fmi2Dll::fmi2GetString)?;
+        Ok(values.map(|values| {
+            values
+                .into_iter()
+                .map(|(signal, value)| {
+                    let value = if value.is_null() {
+                        String::new()
+                    } else {
+                        unsafe { CStr::from_ptr(value) }
+                            .to_string_lossy()
+                            .into_owned()
+                    };
+                    (signal, value)
+                })
+                .collect()
+        }))
     }
 
     pub fn set_reals(
         &self,
         value_map: &HashMap<&ScalarVariable, fmi2Real>,
-    ) -> Result<(), FmuError> {
-        self.set(value_map, Fmi2Dll::fmi2SetReal)
+    ) -> Result<fmi2Status, FmuError> {
+        self.set("fmi2SetReal", value_map, Fmi2Dll::fmi2SetReal)
     }
 
+    /// Set the values of integer or `Enumeration` variables.
     pub fn set_integers(
         &self,
         value_map: &HashMap<&ScalarVariable, fmi2Integer>,
-    ) -> Result<(), FmuError> {
-        self.set(value_map, Fmi2Dll::fmi2SetInteger)
+    ) -> Result<fmi2Status, FmuError> {
+        self.set("fmi2SetInteger", value_map, Fmi2Dll::fmi2SetInteger)
     }
 
     pub fn set_booleans(
         &self,
         value_map: &HashMap<&ScalarVariable, fmi2Integer>,
-    ) -> Result<(), FmuError> {
-        self.set(value_map, Fmi2Dll::fmi2SetBoolean)
+    ) -> Result<fmi2Status, FmuError> {
+        self.set("fmi2SetBoolean", value_map, Fmi2Dll::fmi2SetBoolean)
     }
 
+    /// Set the values of string variables.
+    ///
+    /// Fails with [`FmuError::NulInString`] if a value contains a nul byte.
+    pub fn set_strings<S: AsRef<str>>(
+        &self,
+        value_map: &HashMap<&ScalarVariable, S>,
+    ) -> Result<fmi2Status, FmuError> {
+        let cstrings = value_map
+            .iter()
+            .map(|(signal, value)| Ok((*signal, CString::new(value.as_ref())?)))
+            .collect::<Result<Vec<_>, NulError>>()?;
+
+        // The FMU copies the strings, so they only need to outlive the call.
+        let ptrs: HashMap<&ScalarVariable, fmi2String> = cstrings
+            .iter()
+            .map(|(signal, value)| (*signal, value.as_ptr()))
+            .collect();
+        self.set("fmi2SetString", &ptrs, Fmi2Dll::fmi2SetString)
+    }
+
+    /// Get the values of `Enumeration` variables as the names of their items.
+    pub fn get_enumeration_items<'fmu>(
+        &'fmu self,
+        signals: &[&'fmu ScalarVariable],
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, &'fmu str>>, FmuError> {
+        let model_description = &self.lib.borrow().model_description;
+        let types = signals
+            .iter()
+            .map(|signal| {
+                model_description
+                    .enumeration_type(signal)
+                    .ok_or_else(|| FmuError::NotAnEnumeration(signal.name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let values = self.get_integers(signals)?;
+        let items = zip(signals, types)
+            .map(|(signal, enumeration_type)| {
+                let value = values[signal];
+                match enumeration_type.item_by_value(value.into()) {
+                    Some(item) => Ok((*signal, item.name.as_str())),
+                    None => Err(FmuError::InvalidEnumerationValue {
+                        variable: signal.name.clone(),
+                        value,
+                    }),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(values.map(|_| items))
+    }
+
+    /// Set the values of `Enumeration` variables by the names of their items.
+    pub fn set_enumeration_items(
+        &self,
+        value_map: &HashMap<&ScalarVariable, &str>,
+    ) -> Result<fmi2Status, FmuError> {
+        let model_description = &self.lib.borrow().model_description;
+        let values = value_map
+            .iter()
+            .map(|(signal, name)| {
+                let enumeration_type = model_description
+                    .enumeration_type(signal)
+                    .ok_or_else(|| FmuError::NotAnEnumeration(signal.name.clone()))?;
+                let item = enumeration_type.item_by_name(name).ok_or_else(|| {
+                    FmuError::InvalidEnumerationItem {
+                        variable: signal.name.clone(),
+                        item: name.to_string(),
+                    }
+                })?;
+                let value = fmi2Integer::try_from(item.value).map_err(|_| {
+                    FmuError::InvalidEnumerationItem {
+                        variable: signal.name.clone(),
+                        item: name.to_string(),
+                    }
+                })?;
+                Ok((*signal, value))
+            })
+            .collect::<Result<HashMap<_, _>, FmuError>>()?;
+
+        self.set_integers(&values)
+    }
+
+    /// Call `fmi2DoStep()` to advance a Co-Simulation instance by one communication
+    /// step.
+    ///
+    /// A step that the FMU discards is not an error, see [`StepOutcome`]. A step that
+    /// the FMU runs asynchronously fails regardless of the [`StatusPolicy`], and
+    /// leaves the instance in [`InstanceState::StepInProgress`] until it is canceled
+    /// with [`Self::cancel_step()`]. Use [`Self::do_step_async()`] for such FMU's.
     pub fn do_step(
         &self,
         current_communication_point: fmi2Real,
         communication_step_size: fmi2Real,
         no_set_fmustate_prior_to_current_point: bool,
-    ) -> Result<(), FmuError> {
-        Self::ok_or_err(unsafe {
+    ) -> Result<StepOutcome, FmuError> {
+        self.check_state("fmi2DoStep", &[InstanceState::SlaveInitialized])?;
+        let status = unsafe {
             self.lib.borrow().fmi.fmi2DoStep(
                 self.instance,
                 current_communication_point,
                 communication_step_size,
                 no_set_fmustate_prior_to_current_point as fmi2Boolean,
             )
+        };
+        if status == fmi2Status::fmi2Pending {
+            self.state.set(InstanceState::StepInProgress);
+            let arguments = step_arguments(current_communication_point, communication_step_size);
+            return Err(self.call_error("fmi2DoStep", arguments, status));
+        }
+        self.step_outcome(status, current_communication_point, communication_step_size)
+    }
+
+    /// Interpret the status of `fmi2DoStep()`, querying why the step was discarded.
+    fn step_outcome(
+        &self,
+        status: fmi2Status,
+        current_communication_point: fmi2Real,
+        communication_step_size: fmi2Real,
+    ) -> Result<StepOutcome, FmuError> {
+        match status {
+            fmi2Status::fmi2Discard => {
+                let last_successful_time = self.last_successful_time()?.into_inner();
+                if *self.terminated()? {
+                    Ok(StepOutcome::Terminated {
+                        last_successful_time,
+                    })
+                } else {
+                    Ok(StepOutcome::Discarded {
+                        last_successful_time,
+                    })
+                }
+            }
+            status => {
+                let arguments =
+                    step_arguments(current_communication_point, communication_step_size);
+                let status = self.ok_or_err_with("fmi2DoStep", arguments, status)?;
+                Ok(StepOutcome::Completed { status })
+            }
+        }
+    }
+
+    /// Call `fmi2GetStatus()`.
+    ///
+    /// Only [`StatusKind::DoStepStatus`] is defined for this function.
+    pub fn get_status(&self, kind: StatusKind) -> Result<Checked<fmi2Status>, FmuError> {
+        self.get_status_value(
+            "fmi2GetStatus",
+            kind,
+            fmi2Status::fmi2OK,
+            Fmi2Dll::fmi2GetStatus,
+        )
+    }
+
+    /// Call `fmi2GetRealStatus()`.
+    ///
+    /// Only [`StatusKind::LastSuccessfulTime`] is defined for this function.
+    pub fn get_real_status(&self, kind: StatusKind) -> Result<Checked<fmi2Real>, FmuError> {
+        self.get_status_value("fmi2GetRealStatus", kind, 0.0, Fmi2Dll::fmi2GetRealStatus)
+    }
+
+    /// Call `fmi2GetIntegerStatus()`.
+    ///
+    /// The standard doesn't define any status kind for this function, but an FMU
+    /// may support some of them anyway.
+    pub fn get_integer_status(&self, kind: StatusKind) -> Result<Checked<fmi2Integer>, FmuError> {
+        self.get_status_value(
+            "fmi2GetIntegerStatus",
+            kind,
+            0,
+            Fmi2Dll::fmi2GetIntegerStatus,
+        )
+    }
+
+    /// Call `fmi2GetBooleanStatus()`.
+    ///
+    /// Only [`StatusKind::Terminated`] is defined for this function.
+    pub fn get_boolean_status(&self, kind: StatusKind) -> Result<Checked<bool>, FmuError> {
+        let value = self.get_status_value(
+            "fmi2GetBooleanStatus",
+            kind,
+            0,
+            Fmi2Dll::fmi2GetBooleanStatus,
+        )?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Call `fmi2GetStringStatus()`.
+    ///
+    /// Only [`StatusKind::PendingStatus`] is defined for this function.
+    pub fn get_string_status(&self, kind: StatusKind) -> Result<Checked<String>, FmuError> {
+        let value = self.get_status_value(
+            "fmi2GetStringStatus",
+            kind,
+            std::ptr::null(),
+            Fmi2Dll::fmi2GetStringStatus,
+        )?;
+        Ok(value.map(|value| {
+            if value.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(value) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        }))
+    }
+
+    /// The time up to which the last, discarded, `do_step()` call was computed.
+    pub fn last_successful_time(&self) -> Result<Checked<fmi2Real>, FmuError> {
+        self.get_real_status(StatusKind::LastSuccessfulTime)
+    }
+
+    /// Whether the FMU wants to terminate the simulation.
+    pub fn terminated(&self) -> Result<Checked<bool>, FmuError> {
+        self.get_boolean_status(StatusKind::Terminated)
+    }
+
+    /// Call `fmi2SetRealInputDerivatives()` to set the `order`-th time derivatives
+    /// of Co-Simulation inputs, which the FMU uses to interpolate them during the
+    /// next `do_step()`.
+    pub fn set_real_input_derivatives(
+        &self,
+        order: u32,
+        value_map: &HashMap<&ScalarVariable, fmi2Real>,
+    ) -> Result<fmi2Status, FmuError> {
+        let can_interpolate_inputs = self
+            .lib
+            .borrow()
+            .model_description
+            .co_simulation
+            .as_ref()
+            .is_some_and(|cs| cs.can_interpolate_inputs);
+        if !can_interpolate_inputs {
+            return Err(FmuError::CannotInterpolateInputs);
+        }
+        self.check_state(
+            "fmi2SetRealInputDerivatives",
+            &[
+                InstanceState::InitializationMode,
+                InstanceState::SlaveInitialized,
+            ],
+        )?;
+        self.check_settable(value_map.keys().copied())?;
+        if order == 0 {
+            return Err(FmuError::InvalidDerivativeOrder(order));
+        }
+
+        let (vrs, values): (Vec<_>, Vec<_>) = value_map
+            .iter()
+            .map(|(signal, value)| (signal.value_reference, *value))
+            .unzip();
+        let orders = vec![order as fmi2Integer; vrs.len()];
+
+        let arguments = format_args!(
+            "order={order}, {}",
+            variable_names(value_map.keys().copied())
+        );
+        self.ok_or_err_with("fmi2SetRealInputDerivatives", arguments, unsafe {
+            self.lib.borrow().fmi.fmi2SetRealInputDerivatives(
+                self.instance,
+                vrs.as_ptr(),
+                vrs.len(),
+                orders.as_ptr(),
+                values.as_ptr(),
+            )
+        })
+    }
+
+    /// Call `fmi2GetRealOutputDerivatives()` to get the `order`-th time derivatives
+    /// of Co-Simulation outputs at the current communication point.
+    ///
+    /// `order` must be between 1 and the `maxOutputDerivativeOrder` of the FMU.
+    pub fn get_real_output_derivatives<'fmu>(
+        &'fmu self,
+        order: u32,
+        signals: &[&'fmu ScalarVariable],
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, fmi2Real>>, FmuError> {
+        let max = self
+            .lib
+            .borrow()
+            .model_description
+            .co_simulation
+            .as_ref()
+            .map_or(0, |cs| cs.max_output_derivative_order);
+        if order == 0 {
+            return Err(FmuError::InvalidDerivativeOrder(order));
+        }
+        if order > max {
+            return Err(FmuError::OutputDerivativeOrder { order, max });
+        }
+        self.check_state(
+            "fmi2GetRealOutputDerivatives",
+            &[InstanceState::SlaveInitialized, InstanceState::Terminated],
+        )?;
+
+        let vrs = signals
+            .iter()
+            .map(|s| s.value_reference)
+            .collect::<Vec<_>>();
+        let orders = vec![order as fmi2Integer; vrs.len()];
+        let mut values = vec![0.0; vrs.len()];
+
+        let arguments = format_args!("order={order}, {}", variable_names(signals.iter().copied()));
+        let status = self.ok_or_err_with("fmi2GetRealOutputDerivatives", arguments, unsafe {
+            self.lib.borrow().fmi.fmi2GetRealOutputDerivatives(
+                self.instance,
+                vrs.as_ptr(),
+                vrs.len(),
+                orders.as_ptr(),
+                values.as_mut_ptr(),
+            )
+        })?;
+        Ok(Checked {
+            value: zip(signals.to_owned(), values).collect(),
+            status,
+        })
+    }
+
+    /// Call `fmi2EnterEventMode()` to switch a Model Exchange instance into Event Mode.
+    pub fn enter_event_mode(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state(
+            "fmi2EnterEventMode",
+            &[InstanceState::EventMode, InstanceState::ContinuousTimeMode],
+        )?;
+        let status = self.ok_or_err("fmi2EnterEventMode", unsafe {
+            self.lib.borrow().fmi.fmi2EnterEventMode(self.instance)
+        })?;
+        self.state.set(InstanceState::EventMode);
+        Ok(status)
+    }
+
+    /// Call `fmi2NewDiscreteStates()` to perform one iteration of the event update.
+    ///
+    /// The caller should repeat this call until
+    /// [`EventInfo::new_discrete_states_needed`] is `false`.
+    pub fn new_discrete_states(&self) -> Result<Checked<EventInfo>, FmuError> {
+        self.check_state("fmi2NewDiscreteStates", &[InstanceState::EventMode])?;
+        let mut event_info = fmi2EventInfo {
+            newDiscreteStatesNeeded: false as fmi2Boolean,
+            terminateSimulation: false as fmi2Boolean,
+            nominalsOfContinuousStatesChanged: false as fmi2Boolean,
+            valuesOfContinuousStatesChanged: false as fmi2Boolean,
+            nextEventTimeDefined: false as fmi2Boolean,
+            nextEventTime: 0.0,
+        };
+        let status = self.ok_or_err("fmi2NewDiscreteStates", unsafe {
+            self.lib
+                .borrow()
+                .fmi
+                .fmi2NewDiscreteStates(self.instance, &mut event_info)
+        })?;
+        Ok(Checked {
+            value: event_info.into(),
+            status,
+        })
+    }
+
+    /// Call `fmi2EnterContinuousTimeMode()` to leave Event Mode and start integrating.
+    pub fn enter_continuous_time_mode(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2EnterContinuousTimeMode", &[InstanceState::EventMode])?;
+        let status = self.ok_or_err("fmi2EnterContinuousTimeMode", unsafe {
+            self.lib
+                .borrow()
+                .fmi
+                .fmi2EnterContinuousTimeMode(self.instance)
+        })?;
+        self.state.set(InstanceState::ContinuousTimeMode);
+        Ok(status)
+    }
+
+    /// Call `fmi2CompletedIntegratorStep()` after every accepted integrator step.
+    pub fn completed_integrator_step(
+        &self,
+        no_set_fmustate_prior_to_current_point: bool,
+    ) -> Result<Checked<CompletedIntegratorStep>, FmuError> {
+        self.check_state(
+            "fmi2CompletedIntegratorStep",
+            &[InstanceState::ContinuousTimeMode],
+        )?;
+        let mut enter_event_mode = false as fmi2Boolean;
+        let mut terminate_simulation = false as fmi2Boolean;
+        let status = self.ok_or_err("fmi2CompletedIntegratorStep", unsafe {
+            self.lib.borrow().fmi.fmi2CompletedIntegratorStep(
+                self.instance,
+                no_set_fmustate_prior_to_current_point as fmi2Boolean,
+                &mut enter_event_mode,
+                &mut terminate_simulation,
+            )
+        })?;
+        Ok(Checked {
+            value: CompletedIntegratorStep {
+                enter_event_mode: enter_event_mode != 0,
+                terminate_simulation: terminate_simulation != 0,
+            },
+            status,
+        })
+    }
+
+    /// Call `fmi2SetTime()` to set the independent variable of a Model Exchange instance.
+    pub fn set_time(&self, time: fmi2Real) -> Result<fmi2Status, FmuError> {
+        self.check_state(
+            "fmi2SetTime",
+            &[InstanceState::EventMode, InstanceState::ContinuousTimeMode],
+        )?;
+        self.ok_or_err_with("fmi2SetTime", format_args!("time={time}"), unsafe {
+            self.lib.borrow().fmi.fmi2SetTime(self.instance, time)
+        })
+    }
+
+    /// Get the continuous state vector.
+    pub fn get_continuous_states(&self) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        self.check_state("fmi2GetContinuousStates", state::GET_CONTINUOUS)?;
+        self.get_vector(
+            "fmi2GetContinuousStates",
+            self.lib.borrow().model_description.continuous_state_count(),
+            Fmi2Dll::fmi2GetContinuousStates,
+        )
+    }
+
+    /// Set the continuous state vector.
+    ///
+    /// The length of `states` must match the number of continuous states in the
+    /// model description.
+    pub fn set_continuous_states(&self, states: &[fmi2Real]) -> Result<fmi2Status, FmuError> {
+        self.check_state(
+            "fmi2SetContinuousStates",
+            &[InstanceState::ContinuousTimeMode],
+        )?;
+        let expected = self.lib.borrow().model_description.continuous_state_count();
+        if states.len() != expected {
+            return Err(FmuError::ContinuousStatesLength {
+                expected,
+                actual: states.len(),
+            });
+        }
+
+        self.ok_or_err("fmi2SetContinuousStates", unsafe {
+            self.lib.borrow().fmi.fmi2SetContinuousStates(
+                self.instance,
+                states.as_ptr(),
+                states.len(),
+            )
+        })
+    }
+
+    /// Get the state derivatives at the current time and states.
+    pub fn get_derivatives(&self) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        self.check_state("fmi2GetDerivatives", state::GET_CONTINUOUS)?;
+        self.get_vector(
+            "fmi2GetDerivatives",
+            self.lib.borrow().model_description.continuous_state_count(),
+            Fmi2Dll::fmi2GetDerivatives,
+        )
+    }
+
+    /// Get the event indicators at the current time and states.
+    pub fn get_event_indicators(&self) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        self.check_state("fmi2GetEventIndicators", state::GET_CONTINUOUS)?;
+        self.get_vector(
+            "fmi2GetEventIndicators",
+            self.lib.borrow().model_description.event_indicator_count(),
+            Fmi2Dll::fmi2GetEventIndicators,
+        )
+    }
+
+    /// Get the nominal values of the continuous states.
+    pub fn get_nominals_of_continuous_states(&self) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        self.check_state(
+            "fmi2GetNominalsOfContinuousStates",
+            &[
+                InstanceState::Instantiated,
+                InstanceState::EventMode,
+                InstanceState::ContinuousTimeMode,
+                InstanceState::Terminated,
+            ],
+        )?;
+        self.get_vector(
+            "fmi2GetNominalsOfContinuousStates",
+            self.lib.borrow().model_description.continuous_state_count(),
+            Fmi2Dll::fmi2GetNominalsOfContinuousStates,
+        )
+    }
+
+    /// Whether the FMU implements `fmi2GetDirectionalDerivative()` for the simulation
+    /// type it was loaded as.
+    pub fn provides_directional_derivative(&self) -> bool {
+        let lib = self.lib.borrow();
+        match lib.simulation_type {
+            fmi2Type::fmi2ModelExchange => lib
+                .model_description
+                .model_exchange
+                .as_ref()
+                .is_some_and(|me| me.provides_directional_derivative),
+            fmi2Type::fmi2CoSimulation => lib
+                .model_description
+                .co_simulation
+                .as_ref()
+                .is_some_and(|cs| cs.provides_directional_derivative),
+        }
+    }
+
+    /// Call `fmi2GetDirectionalDerivative()` to compute the partial derivatives of
+    /// `unknowns` with respect to `knowns`, multiplied by the `seed` vector.
+    ///
+    /// Seeding a single known with 1.0 gives a column of the Jacobian, see the
+    /// [`jacobian`](crate::jacobian) module to assemble whole Jacobians.
+    pub fn get_directional_derivative(
+        &self,
+        unknowns: &[&ScalarVariable],
+        knowns: &[&ScalarVariable],
+        seed: &[fmi2Real],
+    ) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        if !self.provides_directional_derivative() {
+            return Err(FmuError::DirectionalDerivativeNotProvided);
+        }
+        self.check_state("fmi2GetDirectionalDerivative", state::GET_VALUES)?;
+        if seed.len() != knowns.len() {
+            return Err(FmuError::SeedLength {
+                expected: knowns.len(),
+                actual: seed.len(),
+            });
+        }
+
+        let unknown_vrs = unknowns
+            .iter()
+            .map(|sv| sv.value_reference)
+            .collect::<Vec<_>>();
+        let known_vrs = knowns
+            .iter()
+            .map(|sv| sv.value_reference)
+            .collect::<Vec<_>>();
+        let mut values = vec![0.0; unknowns.len()];
+        let arguments = format_args!(
+            "unknowns=[{}], knowns=[{}]",
+            variable_names(unknowns.iter().copied()),
+            variable_names(knowns.iter().copied())
+        );
+        let status = self.ok_or_err_with("fmi2GetDirectionalDerivative", arguments, unsafe {
+            self.lib.borrow().fmi.fmi2GetDirectionalDerivative(
+                self.instance,
+                unknown_vrs.as_ptr(),
+                unknown_vrs.len(),
+                known_vrs.as_ptr(),
+                known_vrs.len(),
+                seed.as_ptr(),
+                values.as_mut_ptr(),
+            )
+        })?;
+        Ok(Checked {
+            value: values,
+            status,
+        })
+    }
+
+    fn get_vector(
+        &self,
+        function: &'static str,
+        len: usize,
+        func: unsafe fn(&Fmi2Dll, fmi2Component, *mut fmi2Real, usize) -> fmi2Status,
+    ) -> Result<Checked<Vec<fmi2Real>>, FmuError> {
+        let mut values = vec![0.0; len];
+        let status = self.ok_or_err(function, unsafe {
+            func(
+                &self.lib.borrow().fmi,
+                self.instance,
+                values.as_mut_ptr(),
+                len,
+            )
+        })?;
+        Ok(Checked {
+            value: values,
+            status,
         })
     }
 
     fn get<'fmu, T>(
         &'fmu self,
+        function: &'static str,
         signals: &[&'fmu ScalarVariable],
         func: unsafe fn(
             &Fmi2Dll,
@@ -558,9 +1319,10 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
             usize,
             *mut T,
         ) -> fmi2Status,
-    ) -> Result<HashMap<&'fmu ScalarVariable, T>, FmuError> {
+    ) -> Result<Checked<HashMap<&'fmu ScalarVariable, T>>, FmuError> {
+        self.check_state(function, state::GET_VALUES)?;
         let mut values = Vec::<T>::with_capacity(signals.len());
-        match unsafe {
+        let status = unsafe {
             values.set_len(signals.len());
             func(
                 &self.lib.borrow().fmi,
@@ -573,14 +1335,18 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
                 signals.len(),
                 values.as_mut_ptr(),
             )
-        } {
-            fmi2Status::fmi2OK => Ok(zip(signals.to_owned(), values).collect()),
-            status => Err(FmuError::BadFunctionCall(status)),
-        }
+        };
+        let status =
+            self.ok_or_err_with(function, variable_names(signals.iter().copied()), status)?;
+        Ok(Checked {
+            value: zip(signals.to_owned(), values).collect(),
+            status,
+        })
     }
 
     fn set<T: Copy>(
         &self,
+        function: &'static str,
         value_map: &HashMap<&ScalarVariable, T>,
         func: unsafe fn(
             &Fmi2Dll,
@@ -589,7 +1355,9 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
             usize,
             *const T,
         ) -> fmi2Status,
-    ) -> Result<(), FmuError> {
+    ) -> Result<fmi2Status, FmuError> {
+        self.lib.borrow().check_symbol(function)?;
+        self.check_settable(value_map.keys().copied())?;
         let len = value_map.len();
         let mut vrs = Vec::<fmi2ValueReference>::with_capacity(len);
         let mut values = Vec::<T>::with_capacity(len);
@@ -599,31 +1367,182 @@ impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
             values.push(*value);
         }
 
-        Self::ok_or_err(unsafe {
+        self.ok_or_err_with(
+            function,
+            variable_names(value_map.keys().copied()),
+            unsafe {
+                func(
+                    &self.lib.borrow().fmi,
+                    self.instance,
+                    vrs.as_ptr(),
+                    len,
+                    values.as_ptr(),
+                )
+            },
+        )
+    }
+
+    fn get_status_value<T>(
+        &self,
+        function: &'static str,
+        kind: StatusKind,
+        initial: T,
+        func: unsafe fn(&Fmi2Dll, fmi2Component, fmi2StatusKind, *mut T) -> fmi2Status,
+    ) -> Result<Checked<T>, FmuError> {
+        self.check_state(
+            function,
+            &[
+                InstanceState::SlaveInitialized,
+                InstanceState::StepInProgress,
+                InstanceState::Terminated,
+            ],
+        )?;
+        let mut value = initial;
+        let status = self.ok_or_err_with(function, format_args!("{kind:?}"), unsafe {
             func(
                 &self.lib.borrow().fmi,
                 self.instance,
-                vrs.as_ptr(),
-                len,
-                values.as_ptr(),
+                kind.into(),
+                &mut value,
             )
-        })
+        })?;
+        Ok(Checked { value, status })
     }
 
-    fn ok_or_err(status: fmi2Status) -> Result<(), FmuError> {
-        match status {
-            fmi2Status::fmi2OK => Ok(()),
-            status => Err(FmuError::BadFunctionCall(status)),
+    fn ok_or_err(
+        &self,
+        function: &'static str,
+        status: fmi2Status,
+    ) -> Result<fmi2Status, FmuError> {
+        self.ok_or_err_with(function, "", status)
+    }
+
+    /// The error for a call to `function` that returned `status`, with the recent log
+    /// messages of the instance.
+    fn call_error(
+        &self,
+        function: &'static str,
+        arguments: impl Display,
+        status: fmi2Status,
+    ) -> FmuError {
+        FmuError::BadFunctionCall {
+            function,
+            arguments: arguments.to_string(),
+            status,
+            logs: self.environment.recent_logs(),
         }
     }
 }
 
 impl<C: Borrow<FmuLibrary>> Drop for FmuInstance<C> {
     fn drop(&mut self) {
-        unsafe { self.lib.borrow().fmi.fmi2FreeInstance(self.instance) };
+        if self.state.get() == InstanceState::StepInProgress {
+            // The FMU must not be terminated while a step is pending.
+            let _ = self.cancel_step();
+        }
+        if state::RUNNING.contains(&self.state.get()) {
+            // There is nothing to do about a failure here, and the instance is
+            // freed regardless.
+            let _ = self.terminate();
+        }
+        if self.lib.borrow().exports("fmi2FreeInstance") {
+            unsafe { self.lib.borrow().fmi.fmi2FreeInstance(self.instance) };
+        }
     }
 }
 
+/// The arguments of `fmi2DoStep()`, for error messages.
+fn step_arguments(
+    current_communication_point: fmi2Real,
+    communication_step_size: fmi2Real,
+) -> String {
+    format!(
+        "currentCommunicationPoint={current_communication_point}, \
+         communicationStepSize={communication_step_size}"
+    )
+}
+
+/// The names of `variables`, for error messages.
+fn variable_names<'a>(variables: impl Iterator<Item = &'a ScalarVariable>) -> String {
+    variables.map(|v| v.name.as_str()).join(", ")
+}
+
+/// The result of a Co-Simulation step that didn't fail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOutcome {
+    /// The FMU computed the whole communication step, and returned `status`, which
+    /// is `fmi2OK` unless the [`StatusPolicy`] accepts another status.
+    Completed { status: fmi2Status },
+    /// The FMU only computed the step up to `last_successful_time`. The step can
+    /// be repeated with a smaller step size, for example.
+    Discarded { last_successful_time: fmi2Real },
+    /// The FMU only computed the step up to `last_successful_time`, and requested
+    /// to terminate the simulation.
+    Terminated { last_successful_time: fmi2Real },
+}
+
+/// The kinds of status information that can be queried from a Co-Simulation FMU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusKind {
+    /// The status of an asynchronous `fmi2DoStep()`.
+    DoStepStatus,
+    /// A description of a pending asynchronous step.
+    PendingStatus,
+    /// The time up to which the last discarded step was computed.
+    LastSuccessfulTime,
+    /// Whether the FMU wants to terminate the simulation.
+    Terminated,
+}
+
+impl From<StatusKind> for fmi2StatusKind {
+    fn from(kind: StatusKind) -> Self {
+        match kind {
+            StatusKind::DoStepStatus => fmi2StatusKind::fmi2DoStepStatus,
+            StatusKind::PendingStatus => fmi2StatusKind::fmi2PendingStatus,
+            StatusKind::LastSuccessfulTime => fmi2StatusKind::fmi2LastSuccessfulTime,
+            StatusKind::Terminated => fmi2StatusKind::fmi2Terminated,
+        }
+    }
+}
+
+/// Event information returned by [`FmuInstance::new_discrete_states()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventInfo {
+    /// The event update needs another call to `fmi2NewDiscreteStates()`.
+    pub new_discrete_states_needed: bool,
+    /// The FMU requests the simulation to stop.
+    pub terminate_simulation: bool,
+    /// The nominal values of the continuous states have changed.
+    pub nominals_of_continuous_states_changed: bool,
+    /// The continuous states were re-initialized and must be read again.
+    pub values_of_continuous_states_changed: bool,
+    /// The time of the next scheduled time event, if any.
+    pub next_event_time: Option<fmi2Real>,
+}
+
+impl From<fmi2EventInfo> for EventInfo {
+    fn from(event_info: fmi2EventInfo) -> Self {
+        Self {
+            new_discrete_states_needed: event_info.newDiscreteStatesNeeded != 0,
+            terminate_simulation: event_info.terminateSimulation != 0,
+            nominals_of_continuous_states_changed: event_info.nominalsOfContinuousStatesChanged
+                != 0,
+            values_of_continuous_states_changed: event_info.valuesOfContinuousStatesChanged != 0,
+            next_event_time: (event_info.nextEventTimeDefined != 0)
+                .then_some(event_info.nextEventTime),
+        }
+    }
+}
+
+/// Result of [`FmuInstance::completed_integrator_step()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompletedIntegratorStep {
+    /// The FMU requests to enter Event Mode at the current time.
+    pub enter_event_mode: bool,
+    /// The FMU requests the simulation to stop.
+    pub terminate_simulation: bool,
+}
+
 pub fn outputs_to_string<T: Display>(outputs: &HashMap<&ScalarVariable, T>) -> String {
     let mut s = String::new();
 
@@ -660,12 +1579,68 @@ pub enum FmuLoadError {
 
 #[derive(Error, Debug)]
 pub enum FmuError {
-    #[error("FMU bad function call: {0:?}")]
-    BadFunctionCall(fmi2Status),
+    #[error(
+        "{function}({arguments}) returned {status:?}{}",
+        .logs.iter().map(|r| format!("\n  [{}] {}", r.category, r.message)).join("")
+    )]
+    BadFunctionCall {
+        function: &'static str,
+        arguments: String,
+        status: fmi2Status,
+        /// The last log messages of the instance before the call failed.
+        logs: Vec<FmuLogRecord>,
+    },
     // #[error("FMU load error: {0}")]
     // LoadError(#[from] FmuLoadError),
     #[error("fmi2Instantiate() call failed")]
     FmuInstantiateFailed,
+    #[error("Expected {expected} continuous states, got {actual}")]
+    ContinuousStatesLength { expected: usize, actual: usize },
+    #[error("String value contains a nul byte: {0}")]
+    NulInString(#[from] NulError),
+    #[error("The asynchronous step was canceled")]
+    StepCanceled,
+    #[error("The FMU can not interpolate inputs")]
+    CannotInterpolateInputs,
+    #[error("Derivative order must be at least 1, got {0}")]
+    InvalidDerivativeOrder(u32),
+    #[error("Derivative order {order} exceeds the maxOutputDerivativeOrder of {max}")]
+    OutputDerivativeOrder { order: u32, max: u32 },
+    #[error("The FMU does not provide directional derivatives")]
+    DirectionalDerivativeNotProvided,
+    #[error("Expected a seed of length {expected}, got {actual}")]
+    SeedLength { expected: usize, actual: usize },
+    #[error("Variable {0} is not an Enumeration")]
+    NotAnEnumeration(String),
+    #[error("Enumeration {variable} has no item named {item:?}")]
+    InvalidEnumerationItem { variable: String, item: String },
+    #[error("Enumeration {variable} has no item with value {value}")]
+    InvalidEnumerationValue {
+        variable: String,
+        value: fmi2Integer,
+    },
+    #[error("Log category {0:?} is not declared in the model description")]
+    UnknownLogCategory(String),
+    #[error("The model description has no variable named {0}")]
+    UnknownVariable(String),
+    #[error("Start value {value:?} does not match the type of variable {variable}")]
+    StartValueType { variable: String, value: StartValue },
+    #[error("Start value {value} of variable {variable} is out of the range of fmi2Integer")]
+    StartValueOutOfRange { variable: String, value: i64 },
+    #[error("The FMU does not export {0}()")]
+    MissingSymbol(&'static str),
+    #[error("{function}() is not allowed in state {state:?}")]
+    IllegalCall {
+        function: &'static str,
+        state: InstanceState,
+    },
+    #[error("Variable {variable} with causality {causality:?} and variability {variability:?} can not be set in state {state:?}")]
+    IllegalSet {
+        variable: String,
+        causality: Causality,
+        variability: Variability,
+        state: InstanceState,
+    },
 }
 
 // test module
@@ -691,4 +1666,24 @@ mod tests {
         assert!(matches!(res, Err(FmuUnpackError::InvalidOutputDir { .. })));
         print_err(res.unwrap_err());
     }
+
+    #[test]
+    fn test_bad_function_call() {
+        let err = FmuError::BadFunctionCall {
+            function: "fmi2DoStep",
+            arguments: "currentCommunicationPoint=1, communicationStepSize=0.1".to_string(),
+            status: fmi2Status::fmi2Error,
+            logs: vec![FmuLogRecord {
+                instance_name: "model_0".to_string(),
+                status: fmi2Status::fmi2Error,
+                category: "logStatusError".to_string(),
+                message: "Division by zero".to_string(),
+            }],
+        };
+        assert_eq!(
+            err.to_string(),
+            "fmi2DoStep(currentCommunicationPoint=1, communicationStepSize=0.1) returned fmi2Error\n  [logStatusError] Division by zero"
+        );
+        print_err(err);
+    }
 }
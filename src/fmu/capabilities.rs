@@ -0,0 +1,337 @@
+use super::{FmuError, FmuLibrary};
+use crate::model_description::FmiModelDescription;
+use libfmi::{fmi2Type, Fmi2Dll};
+
+/// Functions that every FMU exports.
+const COMMON_FUNCTIONS: &[&str] = &[
+    "fmi2GetTypesPlatform",
+    "fmi2GetVersion",
+    "fmi2SetDebugLogging",
+    "fmi2Instantiate",
+    "fmi2FreeInstance",
+    "fmi2SetupExperiment",
+    "fmi2EnterInitializationMode",
+    "fmi2ExitInitializationMode",
+    "fmi2Terminate",
+    "fmi2Reset",
+    "fmi2GetReal",
+    "fmi2GetInteger",
+    "fmi2GetBoolean",
+    "fmi2GetString",
+    "fmi2SetReal",
+    "fmi2SetInteger",
+    "fmi2SetBoolean",
+    "fmi2SetString",
+];
+
+/// Functions that every Model Exchange FMU exports.
+const MODEL_EXCHANGE_FUNCTIONS: &[&str] = &[
+    "fmi2EnterEventMode",
+    "fmi2NewDiscreteStates",
+    "fmi2EnterContinuousTimeMode",
+    "fmi2CompletedIntegratorStep",
+    "fmi2SetTime",
+    "fmi2SetContinuousStates",
+    "fmi2GetDerivatives",
+    "fmi2GetEventIndicators",
+    "fmi2GetContinuousStates",
+    "fmi2GetNominalsOfContinuousStates",
+];
+
+/// Functions that every Co-Simulation FMU exports.
+const CO_SIMULATION_FUNCTIONS: &[&str] = &[
+    "fmi2DoStep",
+    "fmi2GetStatus",
+    "fmi2GetRealStatus",
+    "fmi2GetIntegerStatus",
+    "fmi2GetBooleanStatus",
+    "fmi2GetStringStatus",
+];
+
+macro_rules! missing_functions {
+    ($fmi:expr, [$($function:ident),* $(,)?]) => {{
+        let mut missing = Vec::new();
+        $(
+            if $fmi.$function.is_err() {
+                missing.push(stringify!($function));
+            }
+        )*
+        missing
+    }};
+}
+
+/// The FMI functions that failed to load from the binary.
+pub(super) fn missing_functions(fmi: &Fmi2Dll) -> Vec<&'static str> {
+    missing_functions!(
+        fmi,
+        [
+            fmi2GetTypesPlatform,
+            fmi2GetVersion,
+            fmi2SetDebugLogging,
+            fmi2Instantiate,
+            fmi2FreeInstance,
+            fmi2SetupExperiment,
+            fmi2EnterInitializationMode,
+            fmi2ExitInitializationMode,
+            fmi2Terminate,
+            fmi2Reset,
+            fmi2GetReal,
+            fmi2GetInteger,
+            fmi2GetBoolean,
+            fmi2GetString,
+            fmi2SetReal,
+            fmi2SetInteger,
+            fmi2SetBoolean,
+            fmi2SetString,
+            fmi2GetFMUstate,
+            fmi2SetFMUstate,
+            fmi2FreeFMUstate,
+            fmi2SerializedFMUstateSize,
+            fmi2SerializeFMUstate,
+            fmi2DeSerializeFMUstate,
+            fmi2GetDirectionalDerivative,
+            fmi2EnterEventMode,
+            fmi2NewDiscreteStates,
+            fmi2EnterContinuousTimeMode,
+            fmi2CompletedIntegratorStep,
+            fmi2SetTime,
+            fmi2SetContinuousStates,
+            fmi2GetDerivatives,
+            fmi2GetEventIndicators,
+            fmi2GetContinuousStates,
+            fmi2GetNominalsOfContinuousStates,
+            fmi2SetRealInputDerivatives,
+            fmi2GetRealOutputDerivatives,
+            fmi2DoStep,
+            fmi2CancelStep,
+            fmi2GetStatus,
+            fmi2GetRealStatus,
+            fmi2GetIntegerStatus,
+            fmi2GetBooleanStatus,
+            fmi2GetStringStatus,
+        ]
+    )
+}
+
+/// An optional capability of an FMU, see [`Capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    /// The attribute of the model description which declares the capability, e.g.
+    /// `canGetAndSetFMUstate`.
+    pub attribute: &'static str,
+    /// Whether the model description declares the capability.
+    pub declared: bool,
+    /// The functions of the capability.
+    pub functions: &'static [&'static str],
+    /// The functions of the capability that the binary doesn't export.
+    pub missing: Vec<&'static str>,
+}
+
+impl Capability {
+    /// Whether the capability is declared and the binary exports all its functions.
+    pub fn is_available(&self) -> bool {
+        self.declared && self.missing.is_empty()
+    }
+}
+
+/// The FMI functions a binary exports, cross-checked against the capabilities its
+/// model description declares. See [`FmuLibrary::capabilities()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub simulation_type: fmi2Type,
+    /// The functions that every FMU of the simulation type must export, but the
+    /// binary doesn't.
+    pub missing_required: Vec<&'static str>,
+    /// The optional capabilities of the simulation type.
+    pub optional: Vec<Capability>,
+}
+
+impl Capabilities {
+    fn new(
+        model_description: &FmiModelDescription,
+        simulation_type: fmi2Type,
+        missing_functions: &[&'static str],
+    ) -> Self {
+        let missing = |functions: &[&'static str]| {
+            functions
+                .iter()
+                .copied()
+                .filter(|f| missing_functions.contains(f))
+                .collect::<Vec<_>>()
+        };
+        let capability = |attribute, declared, functions| Capability {
+            attribute,
+            declared,
+            functions,
+            missing: missing(functions),
+        };
+
+        let (required, can_get_and_set_fmustate, can_serialize_fmustate, directional) =
+            match simulation_type {
+                fmi2Type::fmi2ModelExchange => {
+                    let me = model_description.model_exchange.as_ref();
+                    (
+                        MODEL_EXCHANGE_FUNCTIONS,
+                        me.is_some_and(|me| me.can_get_and_set_fmustate),
+                        me.is_some_and(|me| me.can_serialize_fmustate),
+                        me.is_some_and(|me| me.provides_directional_derivative),
+                    )
+                }
+                fmi2Type::fmi2CoSimulation => {
+                    let cs = model_description.co_simulation.as_ref();
+                    (
+                        CO_SIMULATION_FUNCTIONS,
+                        cs.is_some_and(|cs| cs.can_get_and_set_fmustate),
+                        cs.is_some_and(|cs| cs.can_serialize_fmustate),
+                        cs.is_some_and(|cs| cs.provides_directional_derivative),
+                    )
+                }
+            };
+
+        let mut optional = vec![
+            capability(
+                "canGetAndSetFMUstate",
+                can_get_and_set_fmustate,
+                &["fmi2GetFMUstate", "fmi2SetFMUstate", "fmi2FreeFMUstate"],
+            ),
+            capability(
+                "canSerializeFMUstate",
+                can_serialize_fmustate,
+                &[
+                    "fmi2SerializedFMUstateSize",
+                    "fmi2SerializeFMUstate",
+                    "fmi2DeSerializeFMUstate",
+                ],
+            ),
+            capability(
+                "providesDirectionalDerivative",
+                directional,
+                &["fmi2GetDirectionalDerivative"],
+            ),
+        ];
+        if simulation_type == fmi2Type::fmi2CoSimulation {
+            let cs = model_description.co_simulation.as_ref();
+            optional.extend([
+                capability(
+                    "canInterpolateInputs",
+                    cs.is_some_and(|cs| cs.can_interpolate_inputs),
+                    &["fmi2SetRealInputDerivatives"],
+                ),
+                capability(
+                    "maxOutputDerivativeOrder",
+                    cs.is_some_and(|cs| cs.max_output_derivative_order > 0),
+                    &["fmi2GetRealOutputDerivatives"],
+                ),
+                capability(
+                    "canRunAsynchronuously",
+                    cs.is_some_and(|cs| cs.can_run_asynchronuously),
+                    &["fmi2CancelStep"],
+                ),
+            ]);
+        }
+
+        let mut missing_required = missing(COMMON_FUNCTIONS);
+        missing_required.extend(missing(required));
+
+        Self {
+            simulation_type,
+            missing_required,
+            optional,
+        }
+    }
+
+    /// The optional capability declared by `attribute`.
+    pub fn get(&self, attribute: &str) -> Option<&Capability> {
+        self.optional.iter().find(|c| c.attribute == attribute)
+    }
+
+    /// The functions the binary should export but doesn't: the required ones and
+    /// those of the declared capabilities.
+    pub fn missing_functions(&self) -> Vec<&'static str> {
+        let declared = self.optional.iter().filter(|c| c.declared);
+        let mut missing = self.missing_required.clone();
+        missing.extend(declared.flat_map(|c| c.missing.iter().copied()));
+        missing
+    }
+}
+
+impl FmuLibrary {
+    /// Report which FMI functions the binary exports, compared with the simulation
+    /// type it was loaded as and the capabilities the model description declares.
+    ///
+    /// Loading succeeds even if functions are missing, and calling one of them
+    /// fails with [`FmuError::MissingSymbol`].
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::new(
+            &self.model_description,
+            self.simulation_type,
+            &self.missing_functions,
+        )
+    }
+
+    /// Whether the binary exports `function`.
+    pub fn exports(&self, function: &str) -> bool {
+        !self.missing_functions.contains(&function)
+    }
+
+    /// Fail with [`FmuError::MissingSymbol`] unless the binary exports `function`.
+    pub(super) fn check_symbol(&self, function: &'static str) -> Result<(), FmuError> {
+        if self.exports(function) {
+            Ok(())
+        } else {
+            Err(FmuError::MissingSymbol(function))
+        }
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_capabilities() {
+        let mut md =
+            FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let capabilities = Capabilities::new(&md, fmi2Type::fmi2CoSimulation, &[]);
+        assert_eq!(capabilities.missing_required, [] as [&str; 0]);
+        assert!(capabilities
+            .get("canGetAndSetFMUstate")
+            .unwrap()
+            .is_available());
+        assert!(!capabilities.get("canRunAsynchronuously").unwrap().declared);
+        assert_eq!(
+            capabilities
+                .get("canInterpolateInputs")
+                .unwrap()
+                .functions
+                .len(),
+            1
+        );
+
+        md.co_simulation.as_mut().unwrap().can_serialize_fmustate = false;
+        let missing = [
+            "fmi2DoStep",
+            "fmi2EnterEventMode",
+            "fmi2SerializeFMUstate",
+            "fmi2GetDirectionalDerivative",
+            "fmi2CancelStep",
+        ];
+        let capabilities = Capabilities::new(&md, fmi2Type::fmi2CoSimulation, &missing);
+        assert_eq!(capabilities.missing_required, ["fmi2DoStep"]);
+        let serialize = capabilities.get("canSerializeFMUstate").unwrap();
+        assert_eq!(serialize.missing, ["fmi2SerializeFMUstate"]);
+        assert!(!serialize.is_available());
+        assert_eq!(
+            capabilities.missing_functions(),
+            ["fmi2DoStep", "fmi2GetDirectionalDerivative"]
+        );
+        assert!(capabilities.get("canInterpolateInputs").is_some());
+
+        // Model Exchange doesn't have the Co-Simulation capabilities.
+        let capabilities = Capabilities::new(&md, fmi2Type::fmi2ModelExchange, &missing);
+        assert_eq!(capabilities.missing_required, ["fmi2EnterEventMode"]);
+        assert!(capabilities.get("canInterpolateInputs").is_none());
+    }
+}
@@ -0,0 +1,476 @@
+use super::{
+    logging::{FmuLogRecord, LogCrateHandler, LogHandler},
+    FmuError, FmuInstance, FmuLibrary, InstanceState, StepOutcome,
+};
+use libfmi::{fmi2Boolean, fmi2ComponentEnvironment, fmi2Real, fmi2Status, logger::LogEnvironment};
+use std::{
+    borrow::Borrow,
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Condvar, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// The number of log messages kept to add to [`FmuError::BadFunctionCall`].
+const RECENT_LOGS: usize = 10;
+
+/// Per-instance state that the FMU's callbacks refer to through the
+/// `componentEnvironment` pointer.
+#[repr(C)]
+pub(super) struct ComponentEnvironment {
+    /// The header through which libfmi's logger finds [`log_message()`]. It must be
+    /// the first field.
+    logger: LogEnvironment,
+    log_handler: Mutex<Box<dyn LogHandler>>,
+    /// The categories enabled with `fmi2SetDebugLogging()`, all of them if `None`.
+    log_categories: Mutex<Option<Vec<String>>>,
+    /// The last [`RECENT_LOGS`] messages, in any category, newest last.
+    recent_logs: Mutex<VecDeque<FmuLogRecord>>,
+    step: Mutex<StepState>,
+    step_finished: Condvar,
+}
+
+impl Default for ComponentEnvironment {
+    fn default() -> Self {
+        Self::new(Box::new(LogCrateHandler))
+    }
+}
+
+#[derive(Debug, Default)]
+struct StepState {
+    /// Counts the asynchronous steps, so that the handle of an earlier step can't
+    /// pick up the result of a later one.
+    generation: u64,
+    /// The status of the last asynchronous step, once it finished.
+    status: Option<fmi2Status>,
+    canceled: bool,
+    waker: Option<Waker>,
+}
+
+impl ComponentEnvironment {
+    pub(super) fn new(log_handler: Box<dyn LogHandler>) -> Self {
+        Self {
+            logger: LogEnvironment { log: log_message },
+            log_handler: Mutex::new(log_handler),
+            log_categories: Mutex::default(),
+            recent_logs: Mutex::default(),
+            step: Mutex::default(),
+            step_finished: Condvar::new(),
+        }
+    }
+
+    /// The pointer to pass as `componentEnvironment` to `fmi2Instantiate()`.
+    ///
+    /// The environment must be boxed so that it doesn't move while the instance
+    /// exists.
+    pub(super) fn as_ptr(&self) -> fmi2ComponentEnvironment {
+        self as *const Self as fmi2ComponentEnvironment
+    }
+
+    /// Only pass on messages in `categories` to the log handler, or all of them if
+    /// `None`.
+    pub(super) fn set_log_categories(&self, categories: Option<Vec<String>>) {
+        *self.log_categories.lock().unwrap() = categories;
+    }
+
+    /// The last log messages of the instance, oldest first.
+    pub(super) fn recent_logs(&self) -> Vec<FmuLogRecord> {
+        let recent_logs = self.recent_logs.lock().unwrap_or_else(|e| e.into_inner());
+        recent_logs.iter().cloned().collect()
+    }
+
+    /// Prepare for a new asynchronous step, and return its generation.
+    fn start_step(&self) -> u64 {
+        let mut step = self.step.lock().unwrap();
+        *step = StepState {
+            generation: step.generation + 1,
+            ..Default::default()
+        };
+        step.generation
+    }
+
+    fn finish_step(&self, update: impl FnOnce(&mut StepState)) {
+        // Called from the FMU, which a panic must not unwind into.
+        let mut step = self.step.lock().unwrap_or_else(|e| e.into_inner());
+        update(&mut step);
+        if let Some(waker) = step.waker.take() {
+            waker.wake();
+        }
+        self.step_finished.notify_all();
+    }
+
+    fn poll_step(
+        &self,
+        generation: u64,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<fmi2Status, FmuError>> {
+        let mut step = self.step.lock().unwrap();
+        match Self::step_result(&step, generation) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                step.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn wait_step(&self, generation: u64) -> Result<fmi2Status, FmuError> {
+        let mut step = self.step.lock().unwrap();
+        loop {
+            if let Some(result) = Self::step_result(&step, generation) {
+                return result;
+            }
+            step = self.step_finished.wait(step).unwrap();
+        }
+    }
+
+    /// The status step `generation` finished with, if it finished. A step that was
+    /// followed by another one must have been canceled.
+    fn step_result(step: &StepState, generation: u64) -> Option<Result<fmi2Status, FmuError>> {
+        if step.canceled || step.generation != generation {
+            return Some(Err(FmuError::StepCanceled));
+        }
+        step.status.map(Ok)
+    }
+}
+
+/// The log callback of the [`LogEnvironment`], called by libfmi's logger with the
+/// formatted message.
+unsafe fn log_message(
+    component_environment: fmi2ComponentEnvironment,
+    instance_name: &str,
+    status: fmi2Status,
+    category: &str,
+    message: &str,
+) {
+    let environment = &*(component_environment as *const ComponentEnvironment);
+    let record = FmuLogRecord {
+        instance_name: instance_name.to_string(),
+        status,
+        category: category.to_string(),
+        message: message.trim_end().to_string(),
+    };
+
+    {
+        let mut recent_logs = environment
+            .recent_logs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if recent_logs.len() == RECENT_LOGS {
+            recent_logs.pop_front();
+        }
+        recent_logs.push_back(record.clone());
+    }
+
+    let enabled = match &*environment
+        .log_categories
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+    {
+        Some(categories) => categories.iter().any(|c| c == category),
+        None => true,
+    };
+    if !enabled {
+        return;
+    }
+
+    let mut handler = environment
+        .log_handler
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    // A panic must not unwind into the FMU, so the message is dropped instead.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| handler.log(&record)));
+}
+
+/// The `stepFinished` callback, called by the FMU when an asynchronous
+/// `fmi2DoStep()` completes.
+pub(super) unsafe extern "C" fn step_finished(
+    component_environment: fmi2ComponentEnvironment,
+    status: fmi2Status,
+) {
+    let environment = &*(component_environment as *const ComponentEnvironment);
+    environment.finish_step(|step| step.status = Some(status));
+}
+
+/// An asynchronous Co-Simulation step started by [`FmuInstance::do_step_async()`].
+///
+/// The handle is a [`Future`] which completes when the FMU reports that the step
+/// finished, and can also be waited on synchronously with [`StepHandle::wait()`].
+#[must_use = "the step may still be running"]
+pub struct StepHandle<'fmu, C: Borrow<FmuLibrary>> {
+    instance: &'fmu FmuInstance<C>,
+    generation: u64,
+    current_communication_point: fmi2Real,
+    communication_step_size: fmi2Real,
+}
+
+impl<'fmu, C: Borrow<FmuLibrary>> StepHandle<'fmu, C> {
+    /// Whether the step has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        let step = self.instance.environment.step.lock().unwrap();
+        ComponentEnvironment::step_result(&step, self.generation).is_some()
+    }
+
+    /// Block until the step finishes.
+    pub fn wait(self) -> Result<StepOutcome, FmuError> {
+        let status = self.instance.environment.wait_step(self.generation);
+        self.instance.end_step(self.generation);
+        self.instance.step_outcome(
+            status?,
+            self.current_communication_point,
+            self.communication_step_size,
+        )
+    }
+
+    /// Call `fmi2CancelStep()` to stop the step.
+    ///
+    /// The handle then completes with [`FmuError::StepCanceled`], unless the step
+    /// already finished. Fails with [`FmuError::IllegalCall`] if the FMU didn't
+    /// return `fmi2Pending` for the step.
+    pub fn cancel(&self) -> Result<fmi2Status, FmuError> {
+        self.instance.cancel_step()
+    }
+}
+
+impl<'fmu, C: Borrow<FmuLibrary>> Future for StepHandle<'fmu, C> {
+    type Output = Result<StepOutcome, FmuError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let poll = self.instance.environment.poll_step(self.generation, cx);
+        poll.map(|status| {
+            self.instance.end_step(self.generation);
+            self.instance.step_outcome(
+                status?,
+                self.current_communication_point,
+                self.communication_step_size,
+            )
+        })
+    }
+}
+
+impl<'fmu, C: Borrow<FmuLibrary>> Drop for StepHandle<'fmu, C> {
+    /// A step that finished without its result being read no longer blocks the
+    /// instance. A step that is still pending has to be canceled.
+    fn drop(&mut self) {
+        if self.is_finished() {
+            self.instance.end_step(self.generation);
+        }
+    }
+}
+
+impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
+    /// Call `fmi2DoStep()` and return a handle to the step, which runs in the
+    /// background if the FMU returns `fmi2Pending`.
+    ///
+    /// Only FMU's with `canRunAsynchronuously` return `fmi2Pending`. For other FMU's
+    /// the step has already finished when the handle is returned.
+    ///
+    /// While the step is pending the instance is in [`InstanceState::StepInProgress`],
+    /// until the handle completes or is dropped after the step finished, or the
+    /// step is canceled.
+    pub fn do_step_async(
+        &self,
+        current_communication_point: fmi2Real,
+        communication_step_size: fmi2Real,
+        no_set_fmustate_prior_to_current_point: bool,
+    ) -> Result<StepHandle<'_, C>, FmuError> {
+        self.check_state("fmi2DoStep", &[InstanceState::SlaveInitialized])?;
+        let generation = self.environment.start_step();
+
+        let status = unsafe {
+            self.lib.borrow().fmi.fmi2DoStep(
+                self.instance,
+                current_communication_point,
+                communication_step_size,
+                no_set_fmustate_prior_to_current_point as fmi2Boolean,
+            )
+        };
+        match status {
+            fmi2Status::fmi2Pending => self.state.set(InstanceState::StepInProgress),
+            fmi2Status::fmi2OK | fmi2Status::fmi2Warning | fmi2Status::fmi2Discard => {
+                // The status policy is applied when the handle completes.
+                self.environment.finish_step(|step| {
+                    step.status.get_or_insert(status);
+                })
+            }
+            status => {
+                // Errors always fail.
+                self.step_outcome(status, current_communication_point, communication_step_size)?;
+            }
+        }
+
+        Ok(StepHandle {
+            instance: self,
+            generation,
+            current_communication_point,
+            communication_step_size,
+        })
+    }
+
+    /// Call `fmi2CancelStep()` to stop a pending asynchronous step.
+    pub fn cancel_step(&self) -> Result<fmi2Status, FmuError> {
+        self.check_state("fmi2CancelStep", &[InstanceState::StepInProgress])?;
+        let status = self.ok_or_err("fmi2CancelStep", unsafe {
+            self.lib.borrow().fmi.fmi2CancelStep(self.instance)
+        })?;
+        self.environment.finish_step(|step| {
+            if step.status.is_none() {
+                step.canceled = true;
+            }
+        });
+        self.state.set(InstanceState::SlaveInitialized);
+        Ok(status)
+    }
+
+    /// Leave [`InstanceState::StepInProgress`] once the result of step `generation`
+    /// has been read, unless a later step already started.
+    fn end_step(&self, generation: u64) {
+        let current = self.environment.step.lock().unwrap().generation;
+        if current == generation && self.state.get() == InstanceState::StepInProgress {
+            self.state.set(InstanceState::SlaveInitialized);
+        }
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libfmi::logger::callback_environment_logger_handler;
+    use std::{
+        ffi::CStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Wake,
+        thread,
+        time::Duration,
+    };
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_step_finished_callback() {
+        let environment = Box::<ComponentEnvironment>::default();
+        let generation = environment.start_step();
+
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(environment.poll_step(generation, &mut cx).is_pending());
+
+        // The FMU calls back from its own thread.
+        let ptr = environment.as_ptr() as usize;
+        let fmu = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            unsafe { step_finished(ptr as fmi2ComponentEnvironment, fmi2Status::fmi2OK) };
+        });
+
+        assert!(matches!(
+            environment.wait_step(generation),
+            Ok(fmi2Status::fmi2OK)
+        ));
+        fmu.join().unwrap();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            environment.poll_step(generation, &mut cx),
+            Poll::Ready(Ok(fmi2Status::fmi2OK))
+        ));
+
+        let next = environment.start_step();
+        unsafe { step_finished(environment.as_ptr(), fmi2Status::fmi2Discard) };
+        assert!(matches!(
+            environment.wait_step(next),
+            Ok(fmi2Status::fmi2Discard)
+        ));
+        // The handle of an earlier step doesn't pick up the result of a later one.
+        assert!(matches!(
+            environment.wait_step(generation),
+            Err(FmuError::StepCanceled)
+        ));
+
+        let generation = environment.start_step();
+        environment.finish_step(|step| step.canceled = true);
+        assert!(matches!(
+            environment.poll_step(generation, &mut cx),
+            Poll::Ready(Err(FmuError::StepCanceled))
+        ));
+    }
+
+    #[test]
+    fn test_log_handler() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let handler_records = records.clone();
+        let environment = Box::new(ComponentEnvironment::new(Box::new(
+            move |record: &FmuLogRecord| handler_records.lock().unwrap().push(record.clone()),
+        )));
+        let log = |category: &CStr| unsafe {
+            callback_environment_logger_handler(
+                environment.as_ptr(),
+                c"instance".as_ptr(),
+                fmi2Status::fmi2Warning,
+                category.as_ptr(),
+                c"value %d out of range\n".as_ptr(),
+                42,
+            )
+        };
+
+        log(c"logStatusWarning");
+        environment.set_log_categories(Some(vec!["logEvents".to_string()]));
+        log(c"logStatusWarning");
+        log(c"logEvents");
+
+        assert_eq!(
+            *records.lock().unwrap(),
+            ["logStatusWarning", "logEvents"].map(|category| FmuLogRecord {
+                instance_name: "instance".to_string(),
+                status: fmi2Status::fmi2Warning,
+                category: category.to_string(),
+                message: "value 42 out of range".to_string(),
+            })
+        );
+
+        // Messages outside of the enabled categories are kept for errors as well.
+        assert_eq!(environment.recent_logs().len(), 3);
+        for _ in 0..RECENT_LOGS {
+            log(c"logEvents");
+        }
+        assert_eq!(environment.recent_logs().len(), RECENT_LOGS);
+    }
+
+    #[test]
+    fn test_panicking_log_handler() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let handler_count = count.clone();
+        let environment = Box::new(ComponentEnvironment::new(Box::new(
+            move |_: &FmuLogRecord| {
+                if handler_count.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("handler failed");
+                }
+            },
+        )));
+        let log = || unsafe {
+            callback_environment_logger_handler(
+                environment.as_ptr(),
+                c"instance".as_ptr(),
+                fmi2Status::fmi2OK,
+                c"logAll".as_ptr(),
+                c"message".as_ptr(),
+            )
+        };
+
+        // The panic doesn't unwind into the caller, and the handler keeps working.
+        log();
+        log();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,46 @@
+use libfmi::{fmi2Status, logger};
+
+/// A log message of an FMU instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FmuLogRecord {
+    /// The name the instance was instantiated with.
+    pub instance_name: String,
+    pub status: fmi2Status,
+    /// One of the `LogCategories` of the model description, although FMU's are not
+    /// strict about it.
+    pub category: String,
+    pub message: String,
+}
+
+/// A receiver of the log messages of an instance, see
+/// [`FmuInstance::instantiate_with_log_handler()`](crate::FmuInstance::instantiate_with_log_handler).
+///
+/// The FMU may log from other threads, e.g. during an asynchronous step. A message
+/// whose handler panics is dropped, since the panic can't unwind into the FMU.
+pub trait LogHandler: Send {
+    fn log(&mut self, record: &FmuLogRecord);
+}
+
+impl<F: FnMut(&FmuLogRecord) + Send> LogHandler for F {
+    fn log(&mut self, record: &FmuLogRecord) {
+        self(record)
+    }
+}
+
+/// Passes log messages to the [`log`] crate, with the instance name as the target.
+///
+/// This is the handler of instances created with
+/// [`FmuInstance::instantiate()`](crate::FmuInstance::instantiate).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogCrateHandler;
+
+impl LogHandler for LogCrateHandler {
+    fn log(&mut self, record: &FmuLogRecord) {
+        logger::log_message(
+            &record.instance_name,
+            record.status,
+            &record.category,
+            &record.message,
+        );
+    }
+}
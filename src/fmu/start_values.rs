@@ -0,0 +1,293 @@
+use super::{status::worst_status, FmuError, FmuInstance, FmuLibrary, InstanceState};
+use crate::model_description::{
+    Causality, Enumeration, Integer, ScalarVariable, ScalarVariables, SignalType, Variability,
+};
+use libfmi::{fmi2Boolean, fmi2Integer, fmi2Real, fmi2Status};
+use std::{borrow::Borrow, collections::HashMap};
+
+/// A value that replaces the start value of a variable, see [`StartValues`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartValue {
+    Real(fmi2Real),
+    /// The value of an `Integer` or `Enumeration` variable.
+    Integer(fmi2Integer),
+    Boolean(bool),
+    String(String),
+}
+
+impl From<fmi2Real> for StartValue {
+    fn from(value: fmi2Real) -> Self {
+        Self::Real(value)
+    }
+}
+
+impl From<fmi2Integer> for StartValue {
+    fn from(value: fmi2Integer) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<bool> for StartValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<&str> for StartValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+impl From<String> for StartValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+/// Start values that override those of the model description, by variable name.
+///
+/// # Example
+/// ```
+/// # use fmu_runner::StartValues;
+/// let start_values = StartValues::from_iter([("h_start", 10.0), ("e", 0.7)]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StartValues(HashMap<String, StartValue>);
+
+impl StartValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the start value of the variable `name`.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<StartValue>) -> &mut Self {
+        self.0.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StartValue> {
+        self.0.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<N: Into<String>, V: Into<StartValue>> FromIterator<(N, V)> for StartValues {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// The start value of `variable` in the model description.
+///
+/// The start values of `String` variables aren't parsed. Integer start values that
+/// don't fit an `fmi2Integer` fail with [`FmuError::StartValueOutOfRange`].
+fn model_start_value(variable: &ScalarVariable) -> Result<Option<StartValue>, FmuError> {
+    let integer = |value: i64| {
+        fmi2Integer::try_from(value)
+            .map(StartValue::Integer)
+            .map_err(|_| FmuError::StartValueOutOfRange {
+                variable: variable.name.clone(),
+                value,
+            })
+    };
+    Ok(match &variable.signal_type {
+        SignalType::Real(real) => real.start.map(StartValue::Real),
+        SignalType::Integer(Integer { start, .. })
+        | SignalType::Enumeration(Enumeration { start, .. }) => start.map(integer).transpose()?,
+        SignalType::Boolean(boolean) => boolean.start.map(StartValue::Boolean),
+        SignalType::String => None,
+    })
+}
+
+/// The values that [`FmuInstance::apply_start_values()`] sets in `state`.
+fn start_values<'a>(
+    variables: &'a ScalarVariables,
+    state: InstanceState,
+    overrides: &StartValues,
+) -> Result<Vec<(&'a ScalarVariable, StartValue)>, FmuError> {
+    let mut values = Vec::new();
+    let before_initialization = matches!(
+        state,
+        InstanceState::Instantiated | InstanceState::InitializationMode
+    );
+
+    if before_initialization {
+        for variable in variables
+            .iter()
+            .filter(|v| state.can_set(v) && !overrides.0.contains_key(&v.name))
+        {
+            if let Some(value) = model_start_value(variable)? {
+                values.push((variable, value));
+            }
+        }
+    }
+
+    for (name, value) in &overrides.0 {
+        let variable = variables
+            .get(name)
+            .ok_or_else(|| FmuError::UnknownVariable(name.clone()))?;
+        let tunable_parameter = variable.causality == Causality::Parameter
+            && variable.variability == Variability::Tunable;
+        if before_initialization || tunable_parameter {
+            values.push((variable, value.clone()));
+        }
+    }
+
+    Ok(values)
+}
+
+impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
+    /// Set the start values of the model description, replaced by `overrides`.
+    ///
+    /// Before `fmi2EnterInitializationMode()` this sets every variable with
+    /// `initial="exact"` or `initial="approx"` and every input, and in
+    /// initialization mode those with `initial="exact"` and the inputs. After
+    /// initialization only the `overrides` of tunable parameters are set, so the
+    /// same `overrides` can be applied again to change them for the rest of the
+    /// simulation.
+    ///
+    /// Overrides of variables that can't be set before initialization fail with
+    /// [`FmuError::IllegalSet`], and start values of the model description that
+    /// don't fit an `fmi2Integer` with [`FmuError::StartValueOutOfRange`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use fmu_runner::{Fmu, FmuInstance, StartValues, fmi2Type};
+    /// # use std::path::Path;
+    /// let fmu = Fmu::unpack(Path::new("./tests/fmu/bouncing_ball.fmu"))?
+    ///     .load(fmi2Type::fmi2CoSimulation)?;
+    /// let fmu_cs = FmuInstance::instantiate(&fmu, true)?;
+    ///
+    /// fmu_cs.setup_experiment(0.0, None, None)?;
+    /// fmu_cs.apply_start_values(&StartValues::from_iter([("h_start", 10.0)]))?;
+    /// fmu_cs.enter_initialization_mode()?;
+    /// fmu_cs.exit_initialization_mode()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_start_values(&self, overrides: &StartValues) -> Result<fmi2Status, FmuError> {
+        let variables = self.lib.borrow().variables();
+        let values = start_values(variables, self.state.get(), overrides)?;
+
+        let mut reals = HashMap::new();
+        let mut integers = HashMap::new();
+        let mut booleans = HashMap::new();
+        let mut strings = HashMap::new();
+        for (variable, value) in values {
+            match (&variable.signal_type, value) {
+                (SignalType::Real(_), StartValue::Real(value)) => {
+                    reals.insert(variable, value);
+                }
+                (
+                    SignalType::Integer(_) | SignalType::Enumeration(_),
+                    StartValue::Integer(value),
+                ) => {
+                    integers.insert(variable, value);
+                }
+                (SignalType::Boolean(_), StartValue::Boolean(value)) => {
+                    booleans.insert(variable, value as fmi2Boolean);
+                }
+                (SignalType::String, StartValue::String(value)) => {
+                    strings.insert(variable, value);
+                }
+                (_, value) => {
+                    return Err(FmuError::StartValueType {
+                        variable: variable.name.clone(),
+                        value,
+                    })
+                }
+            }
+        }
+
+        let mut status = fmi2Status::fmi2OK;
+        if !reals.is_empty() {
+            status = worst_status(status, self.set_reals(&reals)?);
+        }
+        if !integers.is_empty() {
+            status = worst_status(status, self.set_integers(&integers)?);
+        }
+        if !booleans.is_empty() {
+            status = worst_status(status, self.set_booleans(&booleans)?);
+        }
+        if !strings.is_empty() {
+            status = worst_status(status, self.set_strings(&strings)?);
+        }
+        Ok(status)
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_description::FmiModelDescription;
+    use std::path::Path;
+
+    #[test]
+    fn test_start_values() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let variables = &md.model_variables.scalar_variable;
+        let names = |values: Vec<(&ScalarVariable, StartValue)>| {
+            let mut names = values
+                .into_iter()
+                .map(|(v, value)| (v.name.clone(), value))
+                .collect::<Vec<_>>();
+            names.sort_by(|a, b| a.0.cmp(&b.0));
+            names
+        };
+
+        let overrides = StartValues::from_iter([("h_start", 10.0), ("h_min_m", 0.5)]);
+        let values = start_values(variables, InstanceState::Instantiated, &overrides).unwrap();
+        assert_eq!(
+            names(values),
+            [
+                ("done".to_string(), StartValue::Boolean(false)),
+                ("e".to_string(), StartValue::Real(0.7)),
+                ("h_min_m".to_string(), StartValue::Real(0.5)),
+                ("h_start".to_string(), StartValue::Real(10.0)),
+                ("v_mps".to_string(), StartValue::Real(0.0)),
+            ]
+        );
+
+        // Only the tunable parameter is set after initialization.
+        let values = start_values(variables, InstanceState::SlaveInitialized, &overrides).unwrap();
+        assert_eq!(
+            names(values),
+            [("h_min_m".to_string(), StartValue::Real(0.5))]
+        );
+
+        let overrides = StartValues::from_iter([("x", 1.0)]);
+        assert!(matches!(
+            start_values(variables, InstanceState::Instantiated, &overrides),
+            Err(FmuError::UnknownVariable(name)) if name == "x"
+        ));
+
+        let variable = ScalarVariable {
+            name: "n".to_string(),
+            value_reference: 0,
+            description: String::new(),
+            causality: Causality::Parameter,
+            variability: Variability::Fixed,
+            initial: None,
+            can_handle_multiple_set_per_time_instant: None,
+            annotations: None,
+            signal_type: SignalType::Integer(Integer {
+                start: Some(i64::from(fmi2Integer::MAX) + 1),
+                ..Default::default()
+            }),
+            index: 1,
+        };
+        assert!(matches!(
+            model_start_value(&variable),
+            Err(FmuError::StartValueOutOfRange { variable, value })
+                if variable == "n" && value == 2_147_483_648
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+use super::{FmuError, FmuInstance, FmuLibrary};
+use crate::model_description::{Causality, Initial, ScalarVariable, Variability};
+use std::borrow::Borrow;
+
+/// The states of the FMI 2.0 state machine that an [`FmuInstance`] can be in.
+///
+/// Every FMI call checks the state first, so that calls which the standard doesn't
+/// allow fail with [`FmuError::IllegalCall`] instead of reaching the FMU. The
+/// error states after an FMU returns `fmi2Error` or `fmi2Fatal` are not tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceState {
+    /// After `fmi2Instantiate()` or `fmi2Reset()`.
+    Instantiated,
+    /// Between `fmi2EnterInitializationMode()` and `fmi2ExitInitializationMode()`.
+    InitializationMode,
+    /// A Co-Simulation instance after initialization, ready to do steps.
+    SlaveInitialized,
+    /// A Co-Simulation instance while an asynchronous step is pending, see
+    /// [`FmuInstance::do_step_async()`]. Only the `fmi2GetXXXStatus()` functions and
+    /// `fmi2CancelStep()` can be called.
+    StepInProgress,
+    /// A Model Exchange instance in Event Mode.
+    EventMode,
+    /// A Model Exchange instance in Continuous-Time Mode.
+    ContinuousTimeMode,
+    /// After `fmi2Terminate()`.
+    Terminated,
+}
+
+use InstanceState::*;
+
+/// The states in which variables can be read.
+pub(super) const GET_VALUES: &[InstanceState] = &[
+    InitializationMode,
+    SlaveInitialized,
+    EventMode,
+    ContinuousTimeMode,
+    Terminated,
+];
+
+/// The states in which the continuous states, derivatives and event indicators
+/// of a Model Exchange instance can be read.
+pub(super) const GET_CONTINUOUS: &[InstanceState] = &[
+    InitializationMode,
+    EventMode,
+    ContinuousTimeMode,
+    Terminated,
+];
+
+/// The states between initialization and termination.
+pub(super) const RUNNING: &[InstanceState] = &[SlaveInitialized, EventMode, ContinuousTimeMode];
+
+/// Every state except [`StepInProgress`], for the functions that can otherwise be
+/// called at any time.
+pub(super) const NO_PENDING_STEP: &[InstanceState] = &[
+    Instantiated,
+    InitializationMode,
+    SlaveInitialized,
+    EventMode,
+    ContinuousTimeMode,
+    Terminated,
+];
+
+impl InstanceState {
+    /// Whether `fmi2SetXXX()` may be called on `variable` in this state.
+    pub fn can_set(self, variable: &ScalarVariable) -> bool {
+        let input = variable.causality == Causality::Input;
+        let tunable_parameter = variable.causality == Causality::Parameter
+            && variable.variability == Variability::Tunable;
+        let initial = variable.initial_or_default();
+
+        match self {
+            Instantiated => {
+                variable.variability != Variability::Constant
+                    && (input || matches!(initial, Some(Initial::Exact | Initial::Approx)))
+            }
+            InitializationMode => {
+                variable.variability != Variability::Constant
+                    && (input || initial == Some(Initial::Exact))
+            }
+            SlaveInitialized | EventMode => input || tunable_parameter,
+            ContinuousTimeMode => input && variable.variability == Variability::Continuous,
+            StepInProgress | Terminated => false,
+        }
+    }
+}
+
+impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
+    /// The state of the instance in the FMI state machine.
+    pub fn state(&self) -> InstanceState {
+        self.state.get()
+    }
+
+    /// Fail with [`FmuError::IllegalCall`] unless the instance is in one of the
+    /// `allowed` states, or with [`FmuError::MissingSymbol`] if the FMU doesn't
+    /// export `function`.
+    pub(super) fn check_state(
+        &self,
+        function: &'static str,
+        allowed: &[InstanceState],
+    ) -> Result<(), FmuError> {
+        self.lib.borrow().check_symbol(function)?;
+        let state = self.state.get();
+        if allowed.contains(&state) {
+            Ok(())
+        } else {
+            Err(FmuError::IllegalCall { function, state })
+        }
+    }
+
+    /// Fail with [`FmuError::IllegalSet`] if any of `variables` can't be set in the
+    /// current state.
+    pub(super) fn check_settable<'a>(
+        &self,
+        variables: impl IntoIterator<Item = &'a ScalarVariable>,
+    ) -> Result<(), FmuError> {
+        let state = self.state.get();
+        match variables.into_iter().find(|v| !state.can_set(v)) {
+            Some(variable) => Err(FmuError::IllegalSet {
+                variable: variable.name.clone(),
+                causality: variable.causality,
+                variability: variable.variability,
+                state,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_description::FmiModelDescription;
+    use std::path::Path;
+
+    #[test]
+    fn test_can_set() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let variables = &md.model_variables.scalar_variable;
+        let settable = |name: &str| {
+            [
+                Instantiated,
+                InitializationMode,
+                EventMode,
+                ContinuousTimeMode,
+                Terminated,
+            ]
+            .map(|state| state.can_set(&variables[name]))
+        };
+
+        // Constant
+        assert_eq!(settable("g_mps2"), [false; 5]);
+        // Fixed parameter
+        assert_eq!(settable("h_start"), [true, true, false, false, false]);
+        // Tunable parameter
+        assert_eq!(settable("h_min_m"), [true, true, true, false, false]);
+        // Local state with initial="exact"
+        assert_eq!(settable("v_mps"), [true, true, false, false, false]);
+        // Calculated local
+        assert_eq!(settable("der(v_mps)"), [false; 5]);
+
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/enumeration.xml")).unwrap();
+        let variables = &md.model_variables.scalar_variable;
+        // Discrete input
+        assert!(SlaveInitialized.can_set(&variables["mode"]));
+        assert!(!ContinuousTimeMode.can_set(&variables["mode"]));
+        // Output
+        assert!(!InitializationMode.can_set(&variables["speed"]));
+    }
+}
@@ -0,0 +1,182 @@
+use super::{FmuError, FmuInstance, FmuLibrary};
+use libfmi::fmi2Status;
+use std::{
+    borrow::Borrow,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+};
+
+/// What to do when an FMI function returns a status other than `fmi2OK`, see
+/// [`StatusPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusAction {
+    /// Treat the call as successful.
+    Accept,
+    /// Treat the call as successful, but log a warning with the [`log`] crate.
+    Log,
+    /// Fail with [`FmuError::BadFunctionCall`].
+    Error,
+}
+
+/// How an [`FmuInstance`] treats the statuses between `fmi2OK` and `fmi2Error`.
+///
+/// `fmi2Error` and `fmi2Fatal` are always errors. The default policy treats every
+/// status other than `fmi2OK` as an error.
+///
+/// [`FmuInstance::do_step()`] reports `fmi2Discard` as a
+/// [`StepOutcome`](super::StepOutcome) and fails on `fmi2Pending` regardless of
+/// the policy, and [`FmuInstance::do_step_async()`] handles `fmi2Pending` itself.
+/// Calls return the status they accepted, on its own or in a [`Checked`] value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusPolicy {
+    pub warning: StatusAction,
+    pub discard: StatusAction,
+    pub pending: StatusAction,
+}
+
+impl Default for StatusPolicy {
+    fn default() -> Self {
+        Self {
+            warning: StatusAction::Error,
+            discard: StatusAction::Error,
+            pending: StatusAction::Error,
+        }
+    }
+}
+
+impl StatusPolicy {
+    /// What to do with `status`.
+    pub fn action(&self, status: fmi2Status) -> StatusAction {
+        match status {
+            fmi2Status::fmi2OK => StatusAction::Accept,
+            fmi2Status::fmi2Warning => self.warning,
+            fmi2Status::fmi2Discard => self.discard,
+            fmi2Status::fmi2Pending => self.pending,
+            fmi2Status::fmi2Error | fmi2Status::fmi2Fatal => StatusAction::Error,
+        }
+    }
+}
+
+/// The value returned by an FMI call that succeeded under the [`StatusPolicy`],
+/// with the status the FMU returned.
+///
+/// This is how to tell an accepted `fmi2Warning` from `fmi2OK`. It dereferences
+/// to the value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checked<T> {
+    pub value: T,
+    pub status: fmi2Status,
+}
+
+impl<T> Checked<T> {
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Checked<U> {
+        Checked {
+            value: f(self.value),
+            status: self.status,
+        }
+    }
+}
+
+impl<T> Deref for Checked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Checked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// The more severe of two accepted statuses, for calls that make several FMI calls.
+pub(super) fn worst_status(a: fmi2Status, b: fmi2Status) -> fmi2Status {
+    if (b as u32) > (a as u32) {
+        b
+    } else {
+        a
+    }
+}
+
+impl<C: Borrow<FmuLibrary>> FmuInstance<C> {
+    /// The policy for statuses other than `fmi2OK`.
+    pub fn status_policy(&self) -> StatusPolicy {
+        self.status_policy.get()
+    }
+
+    pub fn set_status_policy(&self, policy: StatusPolicy) {
+        self.status_policy.set(policy);
+    }
+
+    /// Apply the status policy to the `status` returned by a call to `function`, and
+    /// return it if it is accepted.
+    pub(super) fn ok_or_err_with(
+        &self,
+        function: &'static str,
+        arguments: impl Display,
+        status: fmi2Status,
+    ) -> Result<fmi2Status, FmuError> {
+        match self.status_policy.get().action(status) {
+            StatusAction::Accept => Ok(status),
+            StatusAction::Log => {
+                log::warn!(
+                    target: &self.instance_name,
+                    "{function}({arguments}) returned {status:?}"
+                );
+                Ok(status)
+            }
+            StatusAction::Error => Err(self.call_error(function, arguments, status)),
+        }
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_policy() {
+        let policy = StatusPolicy::default();
+        assert_eq!(policy.action(fmi2Status::fmi2OK), StatusAction::Accept);
+        assert_eq!(policy.action(fmi2Status::fmi2Warning), StatusAction::Error);
+
+        let policy = StatusPolicy {
+            warning: StatusAction::Log,
+            discard: StatusAction::Accept,
+            ..Default::default()
+        };
+        assert_eq!(policy.action(fmi2Status::fmi2Warning), StatusAction::Log);
+        assert_eq!(policy.action(fmi2Status::fmi2Discard), StatusAction::Accept);
+        assert_eq!(policy.action(fmi2Status::fmi2Pending), StatusAction::Error);
+        assert_eq!(policy.action(fmi2Status::fmi2Fatal), StatusAction::Error);
+    }
+
+    #[test]
+    fn test_checked() {
+        let checked = Checked {
+            value: vec![1.0, 2.0],
+            status: fmi2Status::fmi2Warning,
+        };
+        assert_eq!(checked.len(), 2);
+
+        let checked = checked.map(|values| values.iter().sum::<f64>());
+        assert_eq!(checked.status, fmi2Status::fmi2Warning);
+        assert_eq!(checked.into_inner(), 3.0);
+
+        assert_eq!(
+            worst_status(fmi2Status::fmi2Warning, fmi2Status::fmi2OK),
+            fmi2Status::fmi2Warning
+        );
+        assert_eq!(
+            worst_status(fmi2Status::fmi2Warning, fmi2Status::fmi2Discard),
+            fmi2Status::fmi2Discard
+        );
+    }
+}
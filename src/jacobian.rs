@@ -0,0 +1,338 @@
+//! Assembly of Jacobians from the directional derivatives of an FMU.
+//!
+//! [`FmuInstance::get_directional_derivative()`] computes one Jacobian-vector
+//! product per call. Columns of the Jacobian that don't have a nonzero in a common
+//! row, according to the dependencies in the `ModelStructure`, are computed
+//! together with a single seed vector, so a sparse Jacobian takes far fewer calls
+//! than it has columns.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use fmu_runner::{Fmu, FmuInstance, fmi2Type, jacobian};
+//!
+//! let fmu = Fmu::unpack(Path::new("./model_exchange.fmu"))?
+//!     .load(fmi2Type::fmi2ModelExchange)?;
+//!
+//! let fmu_me = FmuInstance::instantiate(&fmu, true)?;
+//! let signals = fmu_me.lib.variables();
+//!
+//! fmu_me.setup_experiment(0.0, None, None)?;
+//! fmu_me.enter_initialization_mode()?;
+//! fmu_me.exit_initialization_mode()?;
+//! fmu_me.enter_continuous_time_mode()?;
+//!
+//! // The partial derivatives of output y with respect to the inputs u1 and u2.
+//! let d = jacobian::jacobian(&fmu_me, &[&signals["y"]], &[&signals["u1"], &signals["u2"]])?;
+//! println!("dy/du1: {}, dy/du2: {}", d[(0, 0)], d[(0, 1)]);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{
+    model_description::{Causality, FmiModelDescription, ScalarVariable},
+    Checked, FmuError, FmuInstance, FmuLibrary,
+};
+use libfmi::fmi2Real;
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    ops::Index,
+};
+
+/// A dense, row-major Jacobian of `unknowns` with respect to `knowns`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jacobian {
+    pub rows: usize,
+    pub columns: usize,
+    pub values: Vec<fmi2Real>,
+}
+
+impl Jacobian {
+    pub fn zeros(rows: usize, columns: usize) -> Self {
+        Self {
+            rows,
+            columns,
+            values: vec![0.0; rows * columns],
+        }
+    }
+
+    /// The values of row `i`.
+    pub fn row(&self, i: usize) -> &[fmi2Real] {
+        &self.values[i * self.columns..(i + 1) * self.columns]
+    }
+}
+
+impl Index<(usize, usize)> for Jacobian {
+    type Output = fmi2Real;
+
+    fn index(&self, (i, j): (usize, usize)) -> &Self::Output {
+        assert!(i < self.rows && j < self.columns);
+        &self.values[i * self.columns + j]
+    }
+}
+
+/// The structural nonzeros of a Jacobian, as `(row, column, value)` triplets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseJacobian {
+    pub rows: usize,
+    pub columns: usize,
+    pub entries: Vec<(usize, usize, fmi2Real)>,
+}
+
+/// Columns of a Jacobian which can be evaluated together.
+///
+/// Columns which don't have a nonzero in a common row are put in the same group,
+/// so that a single perturbation or directional derivative seed evaluates all of
+/// them at once.
+#[derive(Debug, Clone)]
+pub(crate) struct ColumnGroups {
+    pub(crate) groups: Vec<Vec<usize>>,
+    /// The nonzero rows of every column.
+    pub(crate) rows: Vec<Vec<usize>>,
+}
+
+impl ColumnGroups {
+    pub(crate) fn dense(rows: usize, columns: usize) -> Self {
+        Self {
+            groups: (0..columns).map(|j| vec![j]).collect(),
+            rows: vec![(0..rows).collect(); columns],
+        }
+    }
+
+    /// Group the `columns` of a Jacobian with the nonzero columns of every row
+    /// given by `sparsity`.
+    pub(crate) fn new(sparsity: &[Vec<usize>], columns: usize) -> Self {
+        let mut rows = vec![Vec::new(); columns];
+        for (i, row) in sparsity.iter().enumerate() {
+            for &j in row {
+                rows[j].push(i);
+            }
+        }
+
+        // Greedily put every column in the first group it doesn't overlap with.
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut used_rows: Vec<Vec<bool>> = Vec::new();
+        for (j, column_rows) in rows.iter().enumerate() {
+            let group = match used_rows
+                .iter()
+                .position(|used| column_rows.iter().all(|&i| !used[i]))
+            {
+                Some(group) => group,
+                None => {
+                    groups.push(Vec::new());
+                    used_rows.push(vec![false; sparsity.len()]);
+                    groups.len() - 1
+                }
+            };
+            groups[group].push(j);
+            column_rows.iter().for_each(|&i| used_rows[group][i] = true);
+        }
+
+        Self { groups, rows }
+    }
+
+    /// Assemble the row-major Jacobian from one call to `product` per group, which
+    /// computes the Jacobian-vector product for a seed vector.
+    pub(crate) fn assemble<F>(&self, mut product: F, jacobian: &mut [f64]) -> Result<(), FmuError>
+    where
+        F: FnMut(&[f64]) -> Result<Vec<f64>, FmuError>,
+    {
+        let columns = self.rows.len();
+        let mut seed = vec![0.0; columns];
+        jacobian.fill(0.0);
+
+        for group in &self.groups {
+            group.iter().for_each(|&j| seed[j] = 1.0);
+            let column = product(&seed)?;
+            for &j in group {
+                for &i in &self.rows[j] {
+                    jacobian[i * columns + j] = column[i];
+                }
+                seed[j] = 0.0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The nonzero columns of every row of the Jacobian of `unknowns` with respect to
+/// `knowns`, from the dependencies in the `ModelStructure`.
+///
+/// Unknowns which aren't listed in the `Outputs` or `Derivatives`, or which don't
+/// declare their dependencies, are assumed to depend on all knowns. The
+/// dependencies only list states and inputs, so every unknown is assumed to depend
+/// on the other knowns, such as parameters.
+pub fn sparsity(
+    model_description: &FmiModelDescription,
+    unknowns: &[&ScalarVariable],
+    knowns: &[&ScalarVariable],
+) -> Vec<Vec<usize>> {
+    let structure = &model_description.model_structure;
+    let column_of_index = knowns
+        .iter()
+        .enumerate()
+        .map(|(j, known)| (known.index, j))
+        .collect::<HashMap<_, _>>();
+    let states = model_description
+        .continuous_states()
+        .into_iter()
+        .map(|(state, _)| state.index)
+        .collect::<HashSet<_>>();
+    let dense_columns = knowns
+        .iter()
+        .enumerate()
+        .filter(|(_, known)| known.causality != Causality::Input && !states.contains(&known.index))
+        .map(|(j, _)| j)
+        .collect::<Vec<_>>();
+
+    unknowns
+        .iter()
+        .map(|unknown| {
+            let dependencies = structure
+                .derivatives
+                .unknown
+                .iter()
+                .chain(&structure.outputs.unknown)
+                .find(|u| u.index == unknown.index)
+                .and_then(|u| u.dependencies.as_ref());
+            match dependencies {
+                Some(dependencies) => {
+                    let mut row = dependencies
+                        .iter()
+                        .filter_map(|index| column_of_index.get(index).copied())
+                        .chain(dense_columns.iter().copied())
+                        .collect::<Vec<_>>();
+                    row.sort_unstable();
+                    row.dedup();
+                    row
+                }
+                None => (0..knowns.len()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Compute the Jacobian of `unknowns` with respect to `knowns` at the current
+/// state of `instance` with directional derivatives.
+///
+/// Entries which are zero according to the `ModelStructure` are not evaluated.
+pub fn jacobian<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    unknowns: &[&ScalarVariable],
+    knowns: &[&ScalarVariable],
+) -> Result<Jacobian, FmuError> {
+    let sparsity = sparsity(&instance.lib.borrow().model_description, unknowns, knowns);
+    jacobian_with_sparsity(instance, unknowns, knowns, &sparsity)
+}
+
+fn jacobian_with_sparsity<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    unknowns: &[&ScalarVariable],
+    knowns: &[&ScalarVariable],
+    sparsity: &[Vec<usize>],
+) -> Result<Jacobian, FmuError> {
+    let columns = ColumnGroups::new(sparsity, knowns.len());
+
+    let mut jacobian = Jacobian::zeros(unknowns.len(), knowns.len());
+    columns.assemble(
+        |seed| {
+            instance
+                .get_directional_derivative(unknowns, knowns, seed)
+                .map(Checked::into_inner)
+        },
+        &mut jacobian.values,
+    )?;
+    Ok(jacobian)
+}
+
+/// Like [`jacobian()`], but only return the structural nonzeros.
+pub fn sparse_jacobian<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    unknowns: &[&ScalarVariable],
+    knowns: &[&ScalarVariable],
+) -> Result<SparseJacobian, FmuError> {
+    let sparsity = sparsity(&instance.lib.borrow().model_description, unknowns, knowns);
+    let dense = jacobian_with_sparsity(instance, unknowns, knowns, &sparsity)?;
+
+    let entries = sparsity
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| row.iter().map(move |&j| (i, j)))
+        .map(|(i, j)| (i, j, dense[(i, j)]))
+        .collect();
+    Ok(SparseJacobian {
+        rows: dense.rows,
+        columns: dense.columns,
+        entries,
+    })
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_column_groups() {
+        // A tridiagonal matrix only needs 3 seed vectors.
+        const N: usize = 6;
+        let sparsity = (0..N)
+            .map(|i| (i.saturating_sub(1)..(i + 2).min(N)).collect())
+            .collect::<Vec<Vec<usize>>>();
+        let columns = ColumnGroups::new(&sparsity, N);
+        assert_eq!(columns.groups, [vec![0, 3], vec![1, 4], vec![2, 5]]);
+
+        let matrix = |i: usize, j: usize| match i.abs_diff(j) {
+            0 => (i + 1) as f64,
+            1 => -1.0,
+            _ => 0.0,
+        };
+        let mut products = 0;
+        let mut jacobian = vec![f64::NAN; N * N];
+        columns
+            .assemble(
+                |seed| {
+                    products += 1;
+                    Ok((0..N)
+                        .map(|i| (0..N).map(|j| matrix(i, j) * seed[j]).sum())
+                        .collect())
+                },
+                &mut jacobian,
+            )
+            .unwrap();
+
+        assert_eq!(products, 3);
+        for i in 0..N {
+            for j in 0..N {
+                assert_eq!(jacobian[i * N + j], matrix(i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparsity() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let variables = &md.model_variables.scalar_variable;
+        let states = [&variables["h_m"], &variables["v_mps"]];
+        let derivatives = [
+            &variables["der(h_m)"],
+            &variables["der(v_mps)"],
+            &variables["e"],
+        ];
+
+        assert_eq!(
+            sparsity(&md, &derivatives, &states),
+            [vec![1], vec![0], vec![0, 1]]
+        );
+
+        // The dependencies don't list parameters, so their columns are dense.
+        let knowns = [&variables["h_m"], &variables["e"], &variables["v_mps"]];
+        assert_eq!(
+            sparsity(&md, &derivatives, &knowns),
+            [vec![1, 2], vec![0, 1], vec![0, 1, 2]]
+        );
+    }
+}
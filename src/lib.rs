@@ -34,7 +34,10 @@
 //! ```
 
 mod fmu;
+pub mod jacobian;
+pub mod linearize;
 pub mod model_description;
+pub mod solver;
 
 pub use fmu::*;
 pub use libfmi::fmi2Type;
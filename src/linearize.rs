@@ -0,0 +1,381 @@
+//! Linearization of Model Exchange FMU's into state-space form.
+//!
+//! Around an operating point, the model is approximated by
+//!
+//! ```text
+//! der(x) = A x + B u
+//!      y = C x + D u
+//! ```
+//!
+//! where `x` are the continuous states, `u` the continuous `Real` inputs and `y`
+//! the outputs listed in the `ModelStructure`. Discrete inputs can't change in
+//! Continuous-Time Mode, so they are left out. The matrices are computed with directional
+//! derivatives if the FMU provides them, and with central finite differences
+//! otherwise.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use fmu_runner::{Fmu, FmuInstance, fmi2Type, linearize};
+//!
+//! let fmu = Fmu::unpack(Path::new("./model_exchange.fmu"))?
+//!     .load(fmi2Type::fmi2ModelExchange)?;
+//!
+//! let fmu_me = FmuInstance::instantiate(&fmu, true)?;
+//!
+//! fmu_me.setup_experiment(0.0, None, None)?;
+//! fmu_me.enter_initialization_mode()?;
+//! fmu_me.exit_initialization_mode()?;
+//! fmu_me.enter_continuous_time_mode()?;
+//!
+//! let state_space = linearize::linearize(&fmu_me, &linearize::OperatingPoint::default())?;
+//! std::fs::write("plant.json", state_space.to_json())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{
+    jacobian::{self, ColumnGroups, Jacobian},
+    model_description::{Causality, ScalarVariable, SignalType, Variability},
+    FmuError, FmuInstance, FmuLibrary,
+};
+use libfmi::fmi2Real;
+use std::{borrow::Borrow, collections::HashMap, fmt::Write};
+use thiserror::Error;
+
+/// The point to linearize around.
+///
+/// Anything that is not set keeps its current value in the instance. The `inputs`
+/// must be continuous, since discrete inputs can't be set in Continuous-Time Mode.
+#[derive(Debug, Default, Clone)]
+pub struct OperatingPoint<'fmu> {
+    pub time: Option<fmi2Real>,
+    pub states: Option<Vec<fmi2Real>>,
+    pub inputs: HashMap<&'fmu ScalarVariable, fmi2Real>,
+}
+
+/// The matrices of a linear state-space model, with the names of its variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSpace {
+    pub states: Vec<String>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// `d der(x) / dx`
+    pub a: Jacobian,
+    /// `d der(x) / du`
+    pub b: Jacobian,
+    /// `dy / dx`
+    pub c: Jacobian,
+    /// `dy / du`
+    pub d: Jacobian,
+}
+
+impl StateSpace {
+    /// Format the model as plain text.
+    ///
+    /// The variable names are listed on lines starting with `#`, followed by every
+    /// matrix as a line with its name and dimensions and then one line of
+    /// whitespace separated values per row:
+    ///
+    /// ```text
+    /// # states: x1 x2
+    /// # inputs: u
+    /// # outputs: y
+    /// A 2 2
+    /// 0 1
+    /// -2 -3
+    /// B 2 1
+    /// ...
+    /// ```
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (label, names) in [
+            ("states", &self.states),
+            ("inputs", &self.inputs),
+            ("outputs", &self.outputs),
+        ] {
+            writeln!(text, "# {label}: {}", names.join(" ")).unwrap();
+        }
+        for (name, matrix) in self.matrices() {
+            writeln!(text, "{name} {} {}", matrix.rows, matrix.columns).unwrap();
+            for i in 0..matrix.rows {
+                let row = matrix.row(i).iter().map(|v| v.to_string());
+                writeln!(text, "{}", row.collect::<Vec<_>>().join(" ")).unwrap();
+            }
+        }
+        text
+    }
+
+    /// Format the model as a JSON object with the name lists `states`, `inputs` and
+    /// `outputs`, and the matrices `A`, `B`, `C` and `D` as arrays of rows.
+    pub fn to_json(&self) -> String {
+        let names = |names: &[String]| {
+            let names = names.iter().map(|name| json_string(name));
+            format!("[{}]", names.collect::<Vec<_>>().join(", "))
+        };
+        let matrix = |matrix: &Jacobian| {
+            let rows = (0..matrix.rows).map(|i| {
+                let row = matrix.row(i).iter().map(|v| json_number(*v));
+                format!("[{}]", row.collect::<Vec<_>>().join(", "))
+            });
+            format!("[{}]", rows.collect::<Vec<_>>().join(", "))
+        };
+
+        let mut fields = vec![
+            format!("\"states\": {}", names(&self.states)),
+            format!("\"inputs\": {}", names(&self.inputs)),
+            format!("\"outputs\": {}", names(&self.outputs)),
+        ];
+        for (name, m) in self.matrices() {
+            fields.push(format!("\"{name}\": {}", matrix(m)));
+        }
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+
+    fn matrices(&self) -> [(&str, &Jacobian); 4] {
+        [
+            ("A", &self.a),
+            ("B", &self.b),
+            ("C", &self.c),
+            ("D", &self.d),
+        ]
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// JSON has no representation of NaN and infinity, so those become `null`.
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LinearizeError {
+    #[error(transparent)]
+    Fmu(#[from] FmuError),
+    #[error("The ModelStructure does not list the derivatives of all continuous states")]
+    MissingDerivatives,
+}
+
+/// Linearize a Model Exchange instance in Continuous-Time Mode around
+/// `operating_point`.
+///
+/// The instance is left at the operating point.
+pub fn linearize<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    operating_point: &OperatingPoint,
+) -> Result<StateSpace, LinearizeError> {
+    let model_description = &instance.lib.borrow().model_description;
+    let variables = &model_description.model_variables.scalar_variable;
+
+    let (states, derivatives): (Vec<_>, Vec<_>) =
+        model_description.continuous_states().into_iter().unzip();
+    if states.len() != model_description.continuous_state_count() {
+        return Err(LinearizeError::MissingDerivatives);
+    }
+    let is_real = |sv: &&ScalarVariable| matches!(sv.signal_type, SignalType::Real(_));
+    let inputs = variables
+        .iter()
+        .filter(|sv| sv.causality == Causality::Input)
+        .filter(|sv| sv.variability == Variability::Continuous)
+        .filter(is_real)
+        .collect::<Vec<_>>();
+    let outputs = model_description
+        .model_structure
+        .outputs
+        .unknown
+        .iter()
+        .filter_map(|unknown| model_description.unknown_variable(unknown))
+        .filter(is_real)
+        .collect::<Vec<_>>();
+
+    if let Some(time) = operating_point.time {
+        instance.set_time(time)?;
+    }
+    if let Some(x) = &operating_point.states {
+        instance.set_continuous_states(x)?;
+    }
+    instance.set_reals(&operating_point.inputs)?;
+
+    let unknowns = [&derivatives[..], &outputs[..]].concat();
+    let knowns = [&states[..], &inputs[..]].concat();
+    let full = if instance.provides_directional_derivative() {
+        jacobian::jacobian(instance, &unknowns, &knowns)?
+    } else {
+        finite_difference_linearization(instance, &states, &inputs, &outputs, &unknowns, &knowns)?
+    };
+
+    let (n, m) = (states.len(), inputs.len());
+    let names = |variables: &[&ScalarVariable]| {
+        variables
+            .iter()
+            .map(|sv| sv.name.clone())
+            .collect::<Vec<_>>()
+    };
+    Ok(StateSpace {
+        states: names(&states),
+        inputs: names(&inputs),
+        outputs: names(&outputs),
+        a: submatrix(&full, 0..n, 0..n),
+        b: submatrix(&full, 0..n, n..n + m),
+        c: submatrix(&full, n..unknowns.len(), 0..n),
+        d: submatrix(&full, n..unknowns.len(), n..n + m),
+    })
+}
+
+/// Compute the Jacobian of the derivatives and `outputs` with respect to the
+/// states and `inputs` with central differences, and restore the operating point
+/// afterwards.
+fn finite_difference_linearization<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    states: &[&ScalarVariable],
+    inputs: &[&ScalarVariable],
+    outputs: &[&ScalarVariable],
+    unknowns: &[&ScalarVariable],
+    knowns: &[&ScalarVariable],
+) -> Result<Jacobian, FmuError> {
+    let x0 = instance.get_continuous_states()?.into_inner();
+    let u0 = instance.get_reals(inputs)?;
+    let point = [x0.clone(), inputs.iter().map(|u| u0[u]).collect()].concat();
+
+    let mut evaluate = |point: &[f64]| {
+        let (x, u) = point.split_at(states.len());
+        instance.set_continuous_states(x)?;
+        instance.set_reals(&inputs.iter().copied().zip(u.iter().copied()).collect())?;
+
+        let y = instance.get_reals(outputs)?;
+        let mut values = instance.get_derivatives()?.into_inner();
+        values.extend(outputs.iter().map(|output| y[output]));
+        Ok(values)
+    };
+
+    let sparsity = jacobian::sparsity(&instance.lib.borrow().model_description, unknowns, knowns);
+    let columns = ColumnGroups::new(&sparsity, knowns.len());
+    let mut jacobian = Jacobian::zeros(unknowns.len(), knowns.len());
+    let result = central_differences(&columns, &point, &mut evaluate, &mut jacobian.values);
+
+    evaluate(&point)?;
+    result.map(|_| jacobian)
+}
+
+/// Approximate the row-major Jacobian of `evaluate` at `point` with central
+/// differences.
+fn central_differences<F>(
+    columns: &ColumnGroups,
+    point: &[f64],
+    mut evaluate: F,
+    jacobian: &mut [f64],
+) -> Result<(), FmuError>
+where
+    F: FnMut(&[f64]) -> Result<Vec<f64>, FmuError>,
+{
+    let n = point.len();
+    let step = |x: f64| f64::EPSILON.cbrt() * x.abs().max(1.0);
+    let mut perturbed = point.to_vec();
+    jacobian.fill(0.0);
+
+    for group in &columns.groups {
+        group
+            .iter()
+            .for_each(|&j| perturbed[j] = point[j] + step(point[j]));
+        let forward = evaluate(&perturbed)?;
+        group
+            .iter()
+            .for_each(|&j| perturbed[j] = point[j] - step(point[j]));
+        let backward = evaluate(&perturbed)?;
+
+        for &j in group {
+            for &i in &columns.rows[j] {
+                jacobian[i * n + j] = (forward[i] - backward[i]) / (2.0 * step(point[j]));
+            }
+            perturbed[j] = point[j];
+        }
+    }
+
+    Ok(())
+}
+
+fn submatrix(
+    matrix: &Jacobian,
+    rows: std::ops::Range<usize>,
+    columns: std::ops::Range<usize>,
+) -> Jacobian {
+    let mut sub = Jacobian::zeros(rows.len(), columns.len());
+    for (i, row) in rows.enumerate() {
+        for (j, column) in columns.clone().enumerate() {
+            sub.values[i * sub.columns + j] = matrix[(row, column)];
+        }
+    }
+    sub
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_central_differences() {
+        // f(x) = [x0^3 + x1, x1 x2, x2^2]
+        let f = |x: &[f64]| Ok(vec![x[0].powi(3) + x[1], x[1] * x[2], x[2] * x[2]]);
+        let sparsity = vec![vec![0, 1], vec![1, 2], vec![2]];
+        let columns = ColumnGroups::new(&sparsity, 3);
+
+        let x = [2.0, -1.0, 0.5];
+        let mut jacobian = [0.0; 9];
+        central_differences(&columns, &x, f, &mut jacobian).unwrap();
+
+        let exact = [12.0, 1.0, 0.0, 0.0, 0.5, -1.0, 0.0, 0.0, 1.0];
+        for (value, exact) in jacobian.iter().zip(exact) {
+            assert!((value - exact).abs() < 1e-8, "{value} != {exact}");
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let matrix = |rows, columns, values: &[f64]| Jacobian {
+            rows,
+            columns,
+            values: values.to_vec(),
+        };
+        let state_space = StateSpace {
+            states: vec!["x".to_string(), "v".to_string()],
+            inputs: vec!["F".to_string()],
+            outputs: vec!["pos \"m\"".to_string()],
+            a: matrix(2, 2, &[0.0, 1.0, -2.0, -0.5]),
+            b: matrix(2, 1, &[0.0, 1.0]),
+            c: matrix(1, 2, &[1.0, 0.0]),
+            d: matrix(1, 1, &[f64::NAN]),
+        };
+
+        assert_eq!(
+            state_space.to_text(),
+            "# states: x v\n# inputs: F\n# outputs: pos \"m\"\n\
+             A 2 2\n0 1\n-2 -0.5\nB 2 1\n0\n1\nC 1 2\n1 0\nD 1 1\nNaN\n"
+        );
+        assert_eq!(
+            state_space.to_json(),
+            "{\n  \"states\": [\"x\", \"v\"],\n  \"inputs\": [\"F\"],\n  \
+             \"outputs\": [\"pos \\\"m\\\"\"],\n  \"A\": [[0, 1], [-2, -0.5]],\n  \
+             \"B\": [[0], [1]],\n  \"C\": [[1, 0]],\n  \"D\": [[null]]\n}\n"
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use std::{
     collections::HashMap,
+    fmt::Display,
     fs,
     hash::{Hash, Hasher},
+    ops::Index,
     path::Path,
+    str::FromStr,
 };
 
+use itertools::Itertools;
 use quick_xml::{de::from_str, DeError};
 use serde::{Deserialize, Deserializer};
 
+mod validation;
+
+pub use validation::Violation;
+
 #[derive(Debug, PartialEq, Default, Deserialize)]
 #[serde(default, rename_all = "PascalCase")]
 #[allow(non_snake_case)]
@@ -67,31 +75,87 @@ pub struct UnitDefinitions {
 #[serde(rename_all = "PascalCase")]
 pub struct Real {
     #[serde(rename = "@declaredType")]
-    declared_type: Option<String>,
+    pub declared_type: Option<String>,
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(rename = "@unit")]
+    pub unit: Option<String>,
+    #[serde(rename = "@displayUnit")]
+    pub display_unit: Option<String>,
+    #[serde(rename = "@relativeQuantity")]
+    pub relative_quantity: Option<bool>,
+    #[serde(rename = "@min")]
+    pub min: Option<f64>,
+    #[serde(rename = "@max")]
+    pub max: Option<f64>,
+    #[serde(rename = "@nominal")]
+    pub nominal: Option<f64>,
+    #[serde(rename = "@unbounded")]
+    pub unbounded: Option<bool>,
     #[serde(rename = "@start")]
-    start: Option<f64>,
+    pub start: Option<f64>,
+    /// The name of the state variable this variable is the derivative of.
+    #[serde(skip)]
+    pub derivative: Option<String>,
+    /// The 1-based index of the state variable, as in the `derivative` attribute.
     #[serde(rename = "@derivative")]
-    derivative: Option<usize>,
+    pub derivative_index: Option<usize>,
     #[serde(rename = "@reinit")]
-    reinit: Option<bool>,
+    pub reinit: Option<bool>,
+}
+
+impl Real {
+    /// Fill in the attributes that the variable doesn't set from its declared type.
+    fn inherit(&mut self, real_type: &RealType) {
+        inherit(&mut self.quantity, &real_type.quantity);
+        inherit(&mut self.unit, &real_type.unit);
+        inherit(&mut self.display_unit, &real_type.display_unit);
+        inherit(&mut self.relative_quantity, &real_type.relative_quantity);
+        inherit(&mut self.min, &real_type.min);
+        inherit(&mut self.max, &real_type.max);
+        inherit(&mut self.nominal, &real_type.nominal);
+        inherit(&mut self.unbounded, &real_type.unbounded);
+    }
 }
 
 #[derive(Debug, PartialEq, Default, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Boolean {
     #[serde(rename = "@declaredType")]
-    declared_type: Option<String>,
+    pub declared_type: Option<String>,
     #[serde(rename = "@start")]
-    start: Option<bool>,
+    pub start: Option<bool>,
 }
 
 #[derive(Debug, PartialEq, Default, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Integer {
     #[serde(rename = "@declaredType")]
-    declared_type: Option<String>,
+    pub declared_type: Option<String>,
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(rename = "@min")]
+    pub min: Option<i64>,
+    #[serde(rename = "@max")]
+    pub max: Option<i64>,
+    #[serde(rename = "@start")]
+    pub start: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Enumeration {
+    /// The name of the `SimpleType` that defines the items of this enumeration.
+    #[serde(rename = "@declaredType")]
+    pub declared_type: String,
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(rename = "@min")]
+    pub min: Option<i64>,
+    #[serde(rename = "@max")]
+    pub max: Option<i64>,
     #[serde(rename = "@start")]
-    start: Option<i64>,
+    pub start: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -101,43 +165,145 @@ pub enum SignalType {
     Integer(Integer),
     Boolean(Boolean),
     String,
-    Enumeration,
+    Enumeration(Enumeration),
 }
 
-#[derive(Debug, Deserialize)]
+impl SignalType {
+    /// The name of the `SimpleType` the variable is declared with.
+    pub fn declared_type(&self) -> Option<&str> {
+        match self {
+            SignalType::Real(real) => real.declared_type.as_deref(),
+            SignalType::Integer(integer) => integer.declared_type.as_deref(),
+            SignalType::Boolean(boolean) => boolean.declared_type.as_deref(),
+            SignalType::String => None,
+            SignalType::Enumeration(enumeration) => Some(&enumeration.declared_type),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RealType {
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(rename = "@unit")]
+    pub unit: Option<String>,
+    #[serde(rename = "@displayUnit")]
+    pub display_unit: Option<String>,
+    #[serde(rename = "@relativeQuantity")]
+    pub relative_quantity: Option<bool>,
+    #[serde(rename = "@min")]
+    pub min: Option<f64>,
+    #[serde(rename = "@max")]
+    pub max: Option<f64>,
+    #[serde(rename = "@nominal")]
+    pub nominal: Option<f64>,
+    #[serde(rename = "@unbounded")]
+    pub unbounded: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct IntegerType {
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(rename = "@min")]
+    pub min: Option<i64>,
+    #[serde(rename = "@max")]
+    pub max: Option<i64>,
+}
+
+/// Use the `default` from the declared type if the variable doesn't set a `value`.
+fn inherit<T: Clone>(value: &mut Option<T>, default: &Option<T>) {
+    if value.is_none() {
+        value.clone_from(default);
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EnumerationItem {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@value")]
+    pub value: i64,
+    #[serde(default, rename = "@description")]
+    pub description: String,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EnumerationType {
+    #[serde(rename = "@quantity")]
+    pub quantity: Option<String>,
+    #[serde(default)]
+    pub item: Vec<EnumerationItem>,
+}
+
+impl EnumerationType {
+    /// Look up the item with the given name.
+    pub fn item_by_name(&self, name: &str) -> Option<&EnumerationItem> {
+        self.item.iter().find(|item| item.name == name)
+    }
+
+    /// Look up the item with the given value.
+    pub fn item_by_value(&self, value: i64) -> Option<&EnumerationItem> {
+        self.item.iter().find(|item| item.value == value)
+    }
+}
+
+/// The type specific part of a `SimpleType` definition.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub enum TypeDefinition {
+    Real(RealType),
+    Integer(IntegerType),
+    Boolean,
+    String,
+    Enumeration(EnumerationType),
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SimpleType {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(default, rename = "@description")]
+    pub description: String,
+    #[serde(rename = "$value")]
+    pub definition: TypeDefinition,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct TypeDefinitions {
+    pub simple_type: Vec<SimpleType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Causality {
     Parameter,
     CalculatedParameter,
     Input,
     Output,
+    #[default]
     Local,
     Independent,
 }
 
-impl Default for Causality {
-    fn default() -> Self {
-        Causality::Local
-    }
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Variability {
     Constant,
     Fixed,
     Tunable,
     Discrete,
+    #[default]
     Continuous,
 }
 
-impl Default for Variability {
-    fn default() -> Self {
-        Variability::Continuous
-    }
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Initial {
     Exact,
@@ -165,6 +331,27 @@ pub struct ScalarVariable {
     pub annotations: Option<()>,
     #[serde(rename = "$value")]
     pub signal_type: SignalType,
+    /// The 1-based position of the variable in `ModelVariables`, which is how the
+    /// `ModelStructure` and `derivative` attributes refer to it.
+    #[serde(skip)]
+    pub index: usize,
+}
+
+impl ScalarVariable {
+    /// The `initial` attribute, or its default for the causality and variability of
+    /// the variable if it's not set.
+    ///
+    /// Inputs and the independent variable don't have an `initial` attribute.
+    pub fn initial_or_default(&self) -> Option<Initial> {
+        if self.initial.is_some() {
+            return self.initial;
+        }
+        match (self.causality, self.variability) {
+            (Causality::Input | Causality::Independent, _) => None,
+            (Causality::Parameter, _) | (_, Variability::Constant) => Some(Initial::Exact),
+            _ => Some(Initial::Calculated),
+        }
+    }
 }
 
 impl PartialEq for ScalarVariable {
@@ -180,23 +367,216 @@ impl Hash for ScalarVariable {
     }
 }
 
-fn deserialize_to_map<'de, D>(deserializer: D) -> Result<HashMap<String, ScalarVariable>, D::Error>
+/// The model variables in declaration order, with lookup by name, value reference
+/// and index.
+#[derive(Debug, PartialEq, Default)]
+pub struct ScalarVariables {
+    variables: Vec<ScalarVariable>,
+    by_name: HashMap<String, usize>,
+}
+
+impl ScalarVariables {
+    /// Look up a variable by name.
+    pub fn get(&self, name: &str) -> Option<&ScalarVariable> {
+        self.by_name.get(name).map(|&i| &self.variables[i])
+    }
+
+    /// Look up a variable by its 1-based index, as used by the `ModelStructure` and
+    /// `derivative` attributes.
+    pub fn by_index(&self, index: usize) -> Option<&ScalarVariable> {
+        self.variables.get(index.checked_sub(1)?)
+    }
+
+    /// All variables with the given value reference.
+    ///
+    /// Aliases share a value reference, and variables of different types may use the
+    /// same value reference as well.
+    pub fn by_value_reference(
+        &self,
+        value_reference: ::std::os::raw::c_uint,
+    ) -> impl Iterator<Item = &ScalarVariable> {
+        self.variables
+            .iter()
+            .filter(move |sv| sv.value_reference == value_reference)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    /// Iterate over the variables in declaration order.
+    pub fn iter(&self) -> std::slice::Iter<'_, ScalarVariable> {
+        self.variables.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    fn iter_mut(&mut self) -> std::slice::IterMut<'_, ScalarVariable> {
+        self.variables.iter_mut()
+    }
+}
+
+impl Index<&str> for ScalarVariables {
+    type Output = ScalarVariable;
+
+    fn index(&self, name: &str) -> &Self::Output {
+        self.get(name)
+            .unwrap_or_else(|| panic!("No variable named {name:?}"))
+    }
+}
+
+impl<'a> IntoIterator for &'a ScalarVariables {
+    type Item = &'a ScalarVariable;
+    type IntoIter = std::slice::Iter<'a, ScalarVariable>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn deserialize_variables<'de, D>(deserializer: D) -> Result<ScalarVariables, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let v = Vec::<ScalarVariable>::deserialize(deserializer)?;
-    let mut map = HashMap::new();
-    for item in v {
-        map.insert(item.name.clone(), item);
+    let mut variables = Vec::<ScalarVariable>::deserialize(deserializer)?;
+    let mut by_name = HashMap::new();
+    for (i, sv) in variables.iter_mut().enumerate() {
+        sv.index = i + 1;
+        if by_name.insert(sv.name.clone(), i).is_some() {
+            return Err(serde::de::Error::custom(format!(
+                "duplicate variable name {:?}",
+                sv.name
+            )));
+        }
+    }
+
+    for i in 0..variables.len() {
+        let SignalType::Real(Real {
+            derivative_index: Some(index),
+            ..
+        }) = variables[i].signal_type
+        else {
+            continue;
+        };
+        let state = match index.checked_sub(1).and_then(|i| variables.get(i)) {
+            Some(state) => state.name.clone(),
+            None => {
+                return Err(serde::de::Error::custom(format!(
+                    "{} is the derivative of nonexistent variable {index}",
+                    variables[i].name
+                )))
+            }
+        };
+        if let SignalType::Real(real) = &mut variables[i].signal_type {
+            real.derivative = Some(state);
+        }
     }
-    Ok(map)
+
+    Ok(ScalarVariables { variables, by_name })
 }
 
 #[derive(Debug, PartialEq, Default, Deserialize)]
 #[serde(default, rename_all = "PascalCase")]
 pub struct ModelVariables {
-    #[serde(deserialize_with = "deserialize_to_map")]
-    pub scalar_variable: HashMap<String, ScalarVariable>,
+    #[serde(deserialize_with = "deserialize_variables")]
+    pub scalar_variable: ScalarVariables,
+}
+
+/// Parse a whitespace separated list, such as the variable indices in `dependencies`.
+///
+/// An empty attribute is an empty list, while a missing attribute is handled by the
+/// field's `default`.
+fn deserialize_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .split_whitespace()
+        .map(|item| item.parse().map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// How an unknown depends on a known, from the `dependenciesKind` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// No particular structure, the unknown may depend nonlinearly on the known.
+    Dependent,
+    /// The unknown depends linearly on the known, with a constant factor.
+    Constant,
+    /// Like `Constant`, but the factor is fixed after initialization.
+    Fixed,
+    /// Like `Constant`, but the factor only changes at events of tunable parameters.
+    Tunable,
+    /// Like `Constant`, but the factor only changes at events.
+    Discrete,
+}
+
+impl FromStr for DependencyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dependent" => Ok(Self::Dependent),
+            "constant" => Ok(Self::Constant),
+            "fixed" => Ok(Self::Fixed),
+            "tunable" => Ok(Self::Tunable),
+            "discrete" => Ok(Self::Discrete),
+            _ => Err(format!("unknown dependenciesKind {s:?}")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Unknown {
+    /// Index of the unknown in `ModelVariables`.
+    #[serde(rename = "@index")]
+    pub index: usize,
+    /// Indices of the variables this unknown depends on.
+    ///
+    /// `None` means that it may depend on all knowns.
+    #[serde(
+        default,
+        rename = "@dependencies",
+        deserialize_with = "deserialize_list"
+    )]
+    pub dependencies: Option<Vec<usize>>,
+    /// The kind of each of the `dependencies`.
+    ///
+    /// `None` means that all dependencies are [`DependencyKind::Dependent`].
+    #[serde(
+        default,
+        rename = "@dependenciesKind",
+        deserialize_with = "deserialize_list"
+    )]
+    pub dependencies_kind: Option<Vec<DependencyKind>>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct Unknowns {
+    pub unknown: Vec<Unknown>,
+}
+
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ModelStructure {
+    /// The outputs, with their dependencies on inputs and continuous states.
+    pub outputs: Unknowns,
+    /// The state derivatives, in the order of the continuous state vector.
+    pub derivatives: Unknowns,
+    /// The unknowns that are computed during initialization mode, with their
+    /// dependencies on the variables that can be set before it.
+    pub initial_unknowns: Unknowns,
 }
 
 #[derive(Debug, PartialEq, Default, Deserialize)]
@@ -263,7 +643,7 @@ pub struct CoSimulation {
     #[serde(rename = "@canInterpolateInputs")]
     pub can_interpolate_inputs: bool,
     #[serde(rename = "@maxOutputDerivativeOrder")]
-    pub max_output_derivative_order: bool,
+    pub max_output_derivative_order: u32,
     #[serde(rename = "@canRunAsynchronuously")]
     pub can_run_asynchronuously: bool,
     #[serde(rename = "@canBeInstantiatedOnlyOncePerProcess")]
@@ -300,9 +680,9 @@ pub struct FmiModelDescription {
     pub unit_definitions: Option<UnitDefinitions>,
     pub log_categories: Option<LogCategories>,
     pub default_experiment: Option<DefaultExperiment>,
-    // TypeDefinitions
+    pub model_structure: ModelStructure,
+    pub type_definitions: Option<TypeDefinitions>,
     // VendorAnnotations
-    // ModelStructure
     #[serde(rename = "@fmiVersion")]
     pub fmi_version: String,
     #[serde(rename = "@modelName")]
@@ -326,13 +706,163 @@ pub struct FmiModelDescription {
     #[serde(rename = "@variableNamingConvention")]
     pub variable_naming_convention: String,
     #[serde(rename = "@numberOfEventIndicators")]
-    pub number_of_event_indicators: String,
+    pub number_of_event_indicators: Option<u32>,
 }
 
 impl FmiModelDescription {
     pub fn new(path: &Path) -> Result<Self, DeError> {
         let text = fs::read_to_string(path).unwrap();
-        from_str(&text)
+        let mut model_description: Self = from_str(&text)?;
+        model_description.resolve_declared_types();
+        Ok(model_description)
+    }
+
+    /// Give every variable with a `declaredType` the attributes of that type, unless
+    /// the variable overrides them.
+    fn resolve_declared_types(&mut self) {
+        let Some(type_definitions) = &self.type_definitions else {
+            return;
+        };
+        for sv in self.model_variables.scalar_variable.iter_mut() {
+            let definition = sv.signal_type.declared_type().and_then(|name| {
+                type_definitions
+                    .simple_type
+                    .iter()
+                    .find(|simple_type| simple_type.name == name)
+                    .map(|simple_type| &simple_type.definition)
+            });
+            match (&mut sv.signal_type, definition) {
+                (SignalType::Real(real), Some(TypeDefinition::Real(real_type))) => {
+                    real.inherit(real_type);
+                }
+                (SignalType::Integer(integer), Some(TypeDefinition::Integer(integer_type))) => {
+                    inherit(&mut integer.quantity, &integer_type.quantity);
+                    inherit(&mut integer.min, &integer_type.min);
+                    inherit(&mut integer.max, &integer_type.max);
+                }
+                (
+                    SignalType::Enumeration(enumeration),
+                    Some(TypeDefinition::Enumeration(enumeration_type)),
+                ) => {
+                    inherit(&mut enumeration.quantity, &enumeration_type.quantity);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Look up a `SimpleType` in `TypeDefinitions` by name.
+    pub fn simple_type(&self, name: &str) -> Option<&SimpleType> {
+        self.type_definitions
+            .as_ref()?
+            .simple_type
+            .iter()
+            .find(|simple_type| simple_type.name == name)
+    }
+
+    /// The enumeration type declared by an `Enumeration` variable.
+    pub fn enumeration_type(&self, variable: &ScalarVariable) -> Option<&EnumerationType> {
+        let SignalType::Enumeration(enumeration) = &variable.signal_type else {
+            return None;
+        };
+        match &self.simple_type(&enumeration.declared_type)?.definition {
+            TypeDefinition::Enumeration(enumeration_type) => Some(enumeration_type),
+            _ => None,
+        }
+    }
+
+    /// Look up a variable by its 1-based index in `ModelVariables`.
+    pub fn variable_by_index(&self, index: usize) -> Option<&ScalarVariable> {
+        self.model_variables.scalar_variable.by_index(index)
+    }
+
+    /// The variable of an unknown in `ModelStructure`.
+    pub fn unknown_variable(&self, unknown: &Unknown) -> Option<&ScalarVariable> {
+        self.variable_by_index(unknown.index)
+    }
+
+    /// The variables an unknown in `ModelStructure` depends on, with the kind of
+    /// each dependency.
+    ///
+    /// Returns `None` if the unknown may depend on all knowns.
+    pub fn unknown_dependencies(
+        &self,
+        unknown: &Unknown,
+    ) -> Option<Vec<(&ScalarVariable, DependencyKind)>> {
+        let dependencies = unknown.dependencies.as_ref()?;
+        let kinds = unknown.dependencies_kind.as_deref().unwrap_or_default();
+        Some(
+            dependencies
+                .iter()
+                .enumerate()
+                .filter_map(|(i, index)| {
+                    let kind = kinds.get(i).copied().unwrap_or(DependencyKind::Dependent);
+                    Some((self.variable_by_index(*index)?, kind))
+                })
+                .collect(),
+        )
+    }
+
+    /// The `(state, derivative)` variable pairs, in the order of the continuous
+    /// state vector.
+    ///
+    /// This is empty if the model description doesn't list its derivatives in
+    /// `ModelStructure`.
+    pub fn continuous_states(&self) -> Vec<(&ScalarVariable, &ScalarVariable)> {
+        self.model_structure
+            .derivatives
+            .unknown
+            .iter()
+            .filter_map(|unknown| {
+                let derivative = self.unknown_variable(unknown)?;
+                let SignalType::Real(real) = &derivative.signal_type else {
+                    return None;
+                };
+                let state = self
+                    .model_variables
+                    .scalar_variable
+                    .get(real.derivative.as_ref()?)?;
+                Some((state, derivative))
+            })
+            .collect()
+    }
+
+    /// Look up a category of the `LogCategories` by name.
+    pub fn log_category(&self, name: &str) -> Option<&Category> {
+        self.log_categories
+            .as_ref()?
+            .category
+            .iter()
+            .find(|c| c.name == name)
+    }
+
+    /// Number of continuous states of a Model Exchange model.
+    ///
+    /// This is the number of derivatives listed in `<ModelStructure><Derivatives>`.
+    /// If the list is missing, the states referenced by the `derivative` attributes
+    /// of the `Real` variables are counted instead. Some exporters declare the
+    /// attribute on aliases as well, so duplicate references are only counted once.
+    pub fn continuous_state_count(&self) -> usize {
+        if !self.model_structure.derivatives.unknown.is_empty() {
+            return self.model_structure.derivatives.unknown.len();
+        }
+
+        self.model_variables
+            .scalar_variable
+            .iter()
+            .filter_map(|sv| match &sv.signal_type {
+                SignalType::Real(r) => r.derivative.as_ref(),
+                _ => None,
+            })
+            .unique()
+            .count()
+    }
+
+    /// Number of event indicators of a Model Exchange model.
+    ///
+    /// Defaults to 0 when `numberOfEventIndicators` is not specified.
+    pub fn event_indicator_count(&self) -> usize {
+        self.number_of_event_indicators.unwrap_or(0) as usize
     }
 }
 
@@ -346,6 +876,7 @@ mod tests {
     #[case("./tests/parsing/unit-test.xml")]
     #[case("./tests/parsing/complex-fmi.xml")]
     #[case("./tests/parsing/bouncing-ball.xml")]
+    #[case("./tests/parsing/enumeration.xml")]
     fn test_parsing_model_description(#[case] xml: &str) {
         let text = fs::read_to_string(xml).unwrap();
         let md: FmiModelDescription = from_str(&text).unwrap();
@@ -355,4 +886,199 @@ mod tests {
         println!("{:?}", md.model_variables);
         println!("{:?}", md.model_variables.scalar_variable);
     }
+
+    #[test]
+    fn test_model_exchange_dimensions() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+
+        assert_eq!(md.continuous_state_count(), 2);
+        assert_eq!(md.event_indicator_count(), 4);
+
+        let states = md
+            .continuous_states()
+            .into_iter()
+            .map(|(state, derivative)| (state.name.as_str(), derivative.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(states, [("h_m", "der(h_m)"), ("v_mps", "der(v_mps)")]);
+
+        let dependencies = md
+            .model_structure
+            .derivatives
+            .unknown
+            .iter()
+            .map(|unknown| unknown.dependencies.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dependencies, [Some(vec![7]), Some(vec![5])]);
+
+        let text = fs::read_to_string("./tests/parsing/bouncing-ball.xml").unwrap();
+        let text = text.replace(
+            "numberOfEventIndicators=\"4\"",
+            "numberOfEventIndicators=\"four\"",
+        );
+        assert!(from_str::<FmiModelDescription>(&text).is_err());
+
+        let text = text.replace(" numberOfEventIndicators=\"four\"", "");
+        let md: FmiModelDescription = from_str(&text).unwrap();
+        assert_eq!(md.event_indicator_count(), 0);
+    }
+
+    #[test]
+    fn test_enumeration() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/enumeration.xml")).unwrap();
+        let mode = &md.model_variables.scalar_variable["mode"];
+
+        let SignalType::Enumeration(enumeration) = &mode.signal_type else {
+            panic!("expected an enumeration, got {:?}", mode.signal_type);
+        };
+        assert_eq!(enumeration.declared_type, "DriveMode");
+        assert_eq!(enumeration.start, Some(2));
+
+        let drive_mode = md.enumeration_type(mode).unwrap();
+        assert_eq!(drive_mode.item.len(), 3);
+        assert_eq!(drive_mode.item_by_name("Cruise").unwrap().value, 2);
+        assert_eq!(drive_mode.item_by_value(3).unwrap().name, "Brake");
+        assert!(drive_mode.item_by_name("Reverse").is_none());
+
+        assert!(md
+            .enumeration_type(&md.model_variables.scalar_variable["speed"])
+            .is_none());
+    }
+
+    #[test]
+    fn test_declared_types() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let real = |name: &str| match &md.model_variables.scalar_variable[name].signal_type {
+            SignalType::Real(real) => real,
+            signal_type => panic!("expected a Real, got {signal_type:?}"),
+        };
+
+        let h_m = real("h_m");
+        assert_eq!(h_m.quantity.as_deref(), Some("Length"));
+        assert_eq!(h_m.unit.as_deref(), Some("m"));
+
+        assert_eq!(real("der(v_mps)").unit.as_deref(), Some("m/s2"));
+        assert_eq!(real("der(v_mps)").quantity, None);
+        assert_eq!(real("der(v_mps)").derivative.as_deref(), Some("v_mps"));
+        assert_eq!(real("der(v_mps)").derivative_index, Some(7));
+        assert_eq!(real("h_m").derivative, None);
+
+        // Variable attributes take precedence over the declared type.
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/enumeration.xml")).unwrap();
+        let SignalType::Real(speed) = &md.model_variables.scalar_variable["speed"].signal_type
+        else {
+            panic!("expected a Real");
+        };
+        assert_eq!(speed.quantity.as_deref(), Some("Velocity"));
+        assert_eq!(speed.unit.as_deref(), Some("km/h"));
+    }
+
+    #[test]
+    fn test_model_structure() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let names = |unknown: &Unknown| {
+            let dependencies = md.unknown_dependencies(unknown).map(|dependencies| {
+                dependencies
+                    .into_iter()
+                    .map(|(sv, kind)| (sv.name.as_str(), kind))
+                    .collect::<Vec<_>>()
+            });
+            (
+                md.unknown_variable(unknown).unwrap().name.as_str(),
+                dependencies,
+            )
+        };
+
+        let derivatives = md
+            .model_structure
+            .derivatives
+            .unknown
+            .iter()
+            .map(names)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            derivatives,
+            [
+                ("der(h_m)", Some(vec![("v_mps", DependencyKind::Fixed)])),
+                ("der(v_mps)", Some(vec![("h_m", DependencyKind::Dependent)])),
+            ]
+        );
+
+        let initial_unknowns = md
+            .model_structure
+            .initial_unknowns
+            .unknown
+            .iter()
+            .map(names)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            initial_unknowns,
+            [
+                ("h_m", Some(vec![("h_start", DependencyKind::Dependent)])),
+                ("der(h_m)", Some(vec![("v_mps", DependencyKind::Dependent)])),
+                ("der(v_mps)", Some(vec![])),
+            ]
+        );
+        assert!(md.model_structure.outputs.unknown.is_empty());
+
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/complex-fmi.xml")).unwrap();
+        assert_eq!(md.model_structure.outputs.unknown.len(), 5);
+        assert_eq!(
+            md.unknown_dependencies(&md.model_structure.outputs.unknown[0]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scalar_variables() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let variables = &md.model_variables.scalar_variable;
+
+        let names = variables
+            .iter()
+            .map(|sv| sv.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names[..4], ["g_mps2", "h_start", "h_min_m", "e"]);
+        assert!(variables
+            .iter()
+            .enumerate()
+            .all(|(i, sv)| sv.index == i + 1));
+
+        assert_eq!(variables.by_index(5).unwrap().name, "h_m");
+        assert!(variables.by_index(0).is_none());
+        assert_eq!(variables["h_m"].value_reference, 33554432);
+        assert!(variables.get("x").is_none());
+        assert_eq!(
+            variables
+                .by_value_reference(16777216)
+                .map(|sv| sv.name.as_str())
+                .collect::<Vec<_>>(),
+            ["h_start"]
+        );
+
+        let text = fs::read_to_string("./tests/parsing/enumeration.xml")
+            .unwrap()
+            .replace(r#"name="speed""#, r#"name="mode""#);
+        let err = from_str::<FmiModelDescription>(&text).unwrap_err();
+        assert!(err.to_string().contains("duplicate variable name"), "{err}");
+    }
+
+    #[test]
+    fn test_co_simulation() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        let co_simulation = md.co_simulation.unwrap();
+
+        assert!(co_simulation.can_interpolate_inputs);
+        assert_eq!(co_simulation.max_output_derivative_order, 1);
+    }
+
+    #[test]
+    fn test_log_categories() {
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/complex-fmi.xml")).unwrap();
+
+        assert_eq!(
+            md.log_category("OSMP").unwrap().description,
+            "Enable OSMP-related logging"
+        );
+        assert!(md.log_category("logEvents").is_none());
+    }
 }
@@ -0,0 +1,329 @@
+use super::{
+    Causality, FmiModelDescription, Initial, ScalarVariable, SignalType, Unknowns, Variability,
+};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// A violation of the FMI 2.0 standard found by [`FmiModelDescription::validate()`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum Violation {
+    #[error("fmiVersion is {0:?}, expected \"2.0\"")]
+    FmiVersion(String),
+    #[error(
+        "{base_type} variables {variables:?} share value reference {value_reference}, but {reason}"
+    )]
+    InvalidAlias {
+        base_type: &'static str,
+        value_reference: u32,
+        variables: Vec<String>,
+        reason: &'static str,
+    },
+    #[error(
+        "Variable {variable} combines causality {causality:?} with variability {variability:?}"
+    )]
+    CausalityVariability {
+        variable: String,
+        causality: Causality,
+        variability: Variability,
+    },
+    #[error("Variable {0} is continuous but not a Real")]
+    ContinuousNonReal(String),
+    #[error("Variable {variable} has initial {initial:?}, which is not allowed with causality {causality:?} and variability {variability:?}")]
+    Initial {
+        variable: String,
+        initial: Initial,
+        causality: Causality,
+        variability: Variability,
+    },
+    #[error("Variable {0} requires a start value")]
+    MissingStart(String),
+    #[error("Variable {0} must not have a start value")]
+    UnexpectedStart(String),
+    #[error(
+        "Variable {variable} has derivative {index}, which is not the index of a Real variable"
+    )]
+    DerivativeIndex { variable: String, index: usize },
+    #[error("ModelStructure {list} refers to variable index {index}, which is out of range")]
+    UnknownIndex { list: &'static str, index: usize },
+    #[error("ModelStructure {list} unknown {unknown} depends on variable index {index}, which is out of range")]
+    DependencyIndex {
+        list: &'static str,
+        unknown: usize,
+        index: usize,
+    },
+}
+
+impl FmiModelDescription {
+    /// Check the model description against the rules of the FMI 2.0 standard which
+    /// parsing doesn't enforce.
+    ///
+    /// The start values of `String` variables are not checked, since they aren't
+    /// parsed.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.fmi_version != "2.0" {
+            violations.push(Violation::FmiVersion(self.fmi_version.clone()));
+        }
+
+        let variables = &self.model_variables.scalar_variable;
+        validate_aliases(variables.iter(), &mut violations);
+        for variable in variables {
+            validate_variable(variable, &mut violations);
+
+            if let SignalType::Real(real) = &variable.signal_type {
+                if let Some(index) = real.derivative_index {
+                    let state = variables.by_index(index);
+                    if !matches!(state.map(|s| &s.signal_type), Some(SignalType::Real(_))) {
+                        violations.push(Violation::DerivativeIndex {
+                            variable: variable.name.clone(),
+                            index,
+                        });
+                    }
+                }
+            }
+        }
+
+        let structure = &self.model_structure;
+        for (list, unknowns) in [
+            ("Outputs", &structure.outputs),
+            ("Derivatives", &structure.derivatives),
+            ("InitialUnknowns", &structure.initial_unknowns),
+        ] {
+            validate_unknowns(list, unknowns, variables.len(), &mut violations);
+        }
+
+        violations
+    }
+}
+
+/// The base type that value references are unique within.
+fn base_type(signal_type: &SignalType) -> &'static str {
+    match signal_type {
+        SignalType::Real(_) => "Real",
+        SignalType::Integer(_) | SignalType::Enumeration(_) => "Integer",
+        SignalType::Boolean(_) => "Boolean",
+        SignalType::String => "String",
+    }
+}
+
+/// Whether the variable has a start value, or `None` if that's not known.
+fn has_start(signal_type: &SignalType) -> Option<bool> {
+    match signal_type {
+        SignalType::Real(real) => Some(real.start.is_some()),
+        SignalType::Integer(integer) => Some(integer.start.is_some()),
+        SignalType::Boolean(boolean) => Some(boolean.start.is_some()),
+        SignalType::String => None,
+        SignalType::Enumeration(enumeration) => Some(enumeration.start.is_some()),
+    }
+}
+
+/// Variables that share a value reference are aliases. They must have the same
+/// variability, and only one of them may have a start value, unless they are
+/// constants.
+fn validate_aliases<'a>(
+    variables: impl Iterator<Item = &'a ScalarVariable>,
+    violations: &mut Vec<Violation>,
+) {
+    let mut alias_sets: HashMap<(&'static str, u32), Vec<&ScalarVariable>> = HashMap::new();
+    for variable in variables {
+        alias_sets
+            .entry((base_type(&variable.signal_type), variable.value_reference))
+            .or_default()
+            .push(variable);
+    }
+
+    let mut alias_sets = alias_sets
+        .into_iter()
+        .filter(|(_, set)| set.len() > 1)
+        .collect::<Vec<_>>();
+    // Report in declaration order.
+    alias_sets.sort_by_key(|(_, set)| set[0].index);
+
+    for ((base_type, value_reference), set) in alias_sets {
+        let reason = if set.iter().any(|v| v.variability != set[0].variability) {
+            "their variabilities differ"
+        } else if set[0].variability != Variability::Constant
+            && set
+                .iter()
+                .filter(|v| has_start(&v.signal_type) == Some(true))
+                .count()
+                > 1
+        {
+            "more than one of them has a start value"
+        } else {
+            continue;
+        };
+        violations.push(Violation::InvalidAlias {
+            base_type,
+            value_reference,
+            variables: set.iter().map(|v| v.name.clone()).collect(),
+            reason,
+        });
+    }
+}
+
+/// Check the combination of causality, variability and initial of a variable, and
+/// whether it has a start value when required.
+fn validate_variable(variable: &ScalarVariable, violations: &mut Vec<Violation>) {
+    use Causality::*;
+    use Variability::*;
+
+    let name = || variable.name.clone();
+    let (causality, variability) = (variable.causality, variable.variability);
+
+    let legal = match variability {
+        Constant => matches!(causality, Output | Local),
+        Fixed | Tunable => matches!(causality, Parameter | CalculatedParameter | Local),
+        Discrete => matches!(causality, Input | Output | Local),
+        Continuous => matches!(causality, Input | Output | Local | Independent),
+    };
+    if !legal {
+        violations.push(Violation::CausalityVariability {
+            variable: name(),
+            causality,
+            variability,
+        });
+        // The other rules depend on a legal combination.
+        return;
+    }
+
+    if variability == Continuous && !matches!(variable.signal_type, SignalType::Real(_)) {
+        violations.push(Violation::ContinuousNonReal(name()));
+    }
+
+    if let Some(initial) = variable.initial {
+        let allowed: &[Initial] = match (causality, variability) {
+            (Input | Independent, _) => &[],
+            (Parameter, _) | (_, Constant) => &[Initial::Exact],
+            (CalculatedParameter, _) | (Local, Fixed | Tunable) => {
+                &[Initial::Approx, Initial::Calculated]
+            }
+            _ => &[Initial::Exact, Initial::Approx, Initial::Calculated],
+        };
+        if !allowed.contains(&initial) {
+            violations.push(Violation::Initial {
+                variable: name(),
+                initial,
+                causality,
+                variability,
+            });
+            return;
+        }
+    }
+
+    let start_required = match causality {
+        Input => Some(true),
+        Independent => Some(false),
+        _ => variable
+            .initial_or_default()
+            .map(|initial| initial != Initial::Calculated),
+    };
+    match (start_required, has_start(&variable.signal_type)) {
+        (Some(true), Some(false)) => violations.push(Violation::MissingStart(name())),
+        (Some(false), Some(true)) => violations.push(Violation::UnexpectedStart(name())),
+        _ => {}
+    }
+}
+
+fn validate_unknowns(
+    list: &'static str,
+    unknowns: &Unknowns,
+    variable_count: usize,
+    violations: &mut Vec<Violation>,
+) {
+    let in_range = |index: usize| (1..=variable_count).contains(&index);
+    for unknown in &unknowns.unknown {
+        if !in_range(unknown.index) {
+            violations.push(Violation::UnknownIndex {
+                list,
+                index: unknown.index,
+            });
+        }
+        for &index in unknown.dependencies.iter().flatten() {
+            if !in_range(index) {
+                violations.push(Violation::DependencyIndex {
+                    list,
+                    unknown: unknown.index,
+                    index,
+                });
+            }
+        }
+    }
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_validate() {
+        for path in [
+            "./tests/parsing/bouncing-ball.xml",
+            "./tests/parsing/complex-fmi.xml",
+            "./tests/parsing/enumeration.xml",
+        ] {
+            let md = FmiModelDescription::new(Path::new(path)).unwrap();
+            assert_eq!(md.validate(), [], "{path}");
+        }
+
+        let md = FmiModelDescription::new(Path::new("./tests/parsing/unit-test.xml")).unwrap();
+        assert_eq!(md.validate(), [Violation::FmiVersion("3.0".to_string())]);
+    }
+
+    #[test]
+    fn test_violations() {
+        let mut md =
+            FmiModelDescription::new(Path::new("./tests/parsing/bouncing-ball.xml")).unwrap();
+        md.model_structure.derivatives.unknown[0].index = 42;
+        for variable in md.model_variables.scalar_variable.iter_mut() {
+            match variable.name.as_str() {
+                "h_start" => variable.causality = Causality::Input,
+                "e" => variable.initial = Some(Initial::Calculated),
+                "h_m" => {
+                    variable.value_reference = 33554433;
+                    variable.initial = Some(Initial::Exact);
+                    if let SignalType::Real(real) = &mut variable.signal_type {
+                        real.start = Some(1.0);
+                    }
+                }
+                "der(v_mps)" => {
+                    if let SignalType::Real(real) = &mut variable.signal_type {
+                        real.start = Some(0.0);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(
+            md.validate(),
+            [
+                Violation::InvalidAlias {
+                    base_type: "Real",
+                    value_reference: 33554433,
+                    variables: vec!["h_m".to_string(), "v_mps".to_string()],
+                    reason: "more than one of them has a start value",
+                },
+                Violation::CausalityVariability {
+                    variable: "h_start".to_string(),
+                    causality: Causality::Input,
+                    variability: Variability::Fixed,
+                },
+                Violation::Initial {
+                    variable: "e".to_string(),
+                    initial: Initial::Calculated,
+                    causality: Causality::Parameter,
+                    variability: Variability::Fixed,
+                },
+                Violation::UnexpectedStart("der(v_mps)".to_string()),
+                Violation::UnknownIndex {
+                    list: "Derivatives",
+                    index: 42,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1011 @@
+//! Built-in ODE integrators for simulating Model Exchange FMU's.
+//!
+//! A Model Exchange FMU only provides the right-hand side of its state equations,
+//! so the importer has to integrate them. This module contains a few explicit
+//! integration methods, the implicit [`Bdf`] method for stiff models and a
+//! [`simulate()`] driver which takes care of the FMI event handling around them,
+//! including the location of state events.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use fmu_runner::{Fmu, FmuInstance, fmi2Type, solver};
+//!
+//! let fmu = Fmu::unpack(Path::new("./model_exchange.fmu"))?
+//!     .load(fmi2Type::fmi2ModelExchange)?;
+//!
+//! let fmu_me = FmuInstance::instantiate(&fmu, true)?;
+//! let signals = fmu_me.lib.variables();
+//!
+//! fmu_me.setup_experiment(0.0, Some(10.0), None)?;
+//! fmu_me.enter_initialization_mode()?;
+//! fmu_me.exit_initialization_mode()?;
+//!
+//! let mut integrator = solver::Dopri45::from_model_description(&fmu.model_description);
+//! solver::simulate(&fmu_me, &mut integrator, 0.0, 10.0, |t, fmu_me| {
+//!     let outputs = fmu_me.get_reals(&[&signals["x"]]).unwrap();
+//!     println!("t: {:.3} | {}", t, fmu_runner::outputs_to_string(&outputs));
+//! })?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{
+    jacobian::{self, ColumnGroups},
+    model_description::{FmiModelDescription, ScalarVariable},
+    Checked, EventInfo, FmuError, FmuInstance, FmuLibrary,
+};
+use libfmi::fmi2Real;
+use std::{borrow::Borrow, iter::zip};
+use thiserror::Error;
+
+mod bdf;
+
+pub use bdf::Bdf;
+
+/// Tolerance used by [`Dopri45`] when the model description does not specify one.
+pub const DEFAULT_TOLERANCE: f64 = 1e-4;
+
+/// Relative tolerance to which the time of a state event is located.
+pub const EVENT_TIME_TOLERANCE: f64 = 1e-10;
+
+/// A system of ordinary differential equations `dx/dt = f(t, x)`.
+pub trait OdeSystem {
+    /// Compute the derivatives `dx` at time `t` and states `x`.
+    fn derivatives(&mut self, t: f64, x: &[f64], dx: &mut [f64]) -> Result<(), FmuError>;
+
+    /// Compute the row-major Jacobian `df/dx` at time `t` and states `x`, where
+    /// `dx` are the derivatives at that point.
+    ///
+    /// The default implementation uses forward finite differences.
+    fn jacobian(
+        &mut self,
+        t: f64,
+        x: &[f64],
+        dx: &[f64],
+        jacobian: &mut [f64],
+    ) -> Result<(), FmuError> {
+        finite_difference_jacobian(
+            self,
+            t,
+            x,
+            dx,
+            &ColumnGroups::dense(x.len(), x.len()),
+            jacobian,
+        )
+    }
+}
+
+impl<F> OdeSystem for F
+where
+    F: FnMut(f64, &[f64], &mut [f64]) -> Result<(), FmuError>,
+{
+    fn derivatives(&mut self, t: f64, x: &[f64], dx: &mut [f64]) -> Result<(), FmuError> {
+        self(t, x, dx)
+    }
+}
+
+/// A one-step ODE integration method.
+pub trait Integrator {
+    /// Advance the states `x` from time `t` by at most `h_max`.
+    ///
+    /// Returns the size of the step that was actually taken.
+    fn step(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &mut [f64],
+        h_max: f64,
+    ) -> Result<f64, SolverError>;
+
+    /// Interpolate the states at time `t` within the last accepted step.
+    fn interpolate(&self, t: f64, x: &mut [f64]);
+
+    /// Set the nominal values of the states, used for error control.
+    fn set_nominals(&mut self, _nominals: &[f64]) {}
+
+    /// Discard any information carried over from previous steps.
+    ///
+    /// This is called after every event, since the derivatives may be
+    /// discontinuous there even if the states weren't re-initialized.
+    fn reset(&mut self) {}
+}
+
+/// Forward Euler with a fixed step size.
+#[derive(Debug, Clone)]
+pub struct Euler {
+    pub step_size: f64,
+    t: f64,
+    x: Vec<f64>,
+    dx: Vec<f64>,
+}
+
+impl Euler {
+    pub fn new(step_size: f64) -> Self {
+        Self {
+            step_size,
+            t: 0.0,
+            x: Vec::new(),
+            dx: Vec::new(),
+        }
+    }
+}
+
+impl Integrator for Euler {
+    fn step(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &mut [f64],
+        h_max: f64,
+    ) -> Result<f64, SolverError> {
+        let h = self.step_size.min(h_max);
+        self.dx.resize(x.len(), 0.0);
+        self.t = t;
+        self.x = x.to_vec();
+
+        system.derivatives(t, x, &mut self.dx)?;
+        for (x, dx) in x.iter_mut().zip(&self.dx) {
+            *x += h * dx;
+        }
+
+        Ok(h)
+    }
+
+    fn interpolate(&self, t: f64, x: &mut [f64]) {
+        axpy(x, &self.x, t - self.t, &self.dx);
+    }
+}
+
+/// The classic fourth order Runge-Kutta method with a fixed step size.
+#[derive(Debug, Clone)]
+pub struct Rk4 {
+    pub step_size: f64,
+    t: f64,
+    h: f64,
+    x: Vec<f64>,
+    k: [Vec<f64>; 4],
+    x_stage: Vec<f64>,
+}
+
+impl Rk4 {
+    pub fn new(step_size: f64) -> Self {
+        Self {
+            step_size,
+            t: 0.0,
+            h: 0.0,
+            x: Vec::new(),
+            k: Default::default(),
+            x_stage: Vec::new(),
+        }
+    }
+}
+
+impl Integrator for Rk4 {
+    fn step(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &mut [f64],
+        h_max: f64,
+    ) -> Result<f64, SolverError> {
+        let h = self.step_size.min(h_max);
+        let n = x.len();
+        self.k.iter_mut().for_each(|k| k.resize(n, 0.0));
+        self.x_stage.resize(n, 0.0);
+        self.t = t;
+        self.h = h;
+        self.x = x.to_vec();
+
+        let [k1, k2, k3, k4] = &mut self.k;
+
+        system.derivatives(t, x, k1)?;
+        axpy(&mut self.x_stage, x, 0.5 * h, k1);
+        system.derivatives(t + 0.5 * h, &self.x_stage, k2)?;
+        axpy(&mut self.x_stage, x, 0.5 * h, k2);
+        system.derivatives(t + 0.5 * h, &self.x_stage, k3)?;
+        axpy(&mut self.x_stage, x, h, k3);
+        system.derivatives(t + h, &self.x_stage, k4)?;
+
+        for i in 0..n {
+            x[i] += h / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]);
+        }
+
+        Ok(h)
+    }
+
+    /// Third order continuous extension of RK4.
+    fn interpolate(&self, t: f64, x: &mut [f64]) {
+        let theta = (t - self.t) / self.h;
+        let b1 = theta - 1.5 * theta.powi(2) + 2.0 / 3.0 * theta.powi(3);
+        let b23 = theta.powi(2) - 2.0 / 3.0 * theta.powi(3);
+        let b4 = -0.5 * theta.powi(2) + 2.0 / 3.0 * theta.powi(3);
+
+        let [k1, k2, k3, k4] = &self.k;
+        for i in 0..x.len() {
+            x[i] = self.x[i] + self.h * (b1 * k1[i] + b23 * (k2[i] + k3[i]) + b4 * k4[i]);
+        }
+    }
+}
+
+/// The adaptive Dormand-Prince 5(4) Runge-Kutta method.
+///
+/// The local error of every step is kept below `relative_tolerance * |x| +
+/// absolute_tolerance`, where the absolute tolerance of each state is the relative
+/// tolerance scaled by the state's nominal value.
+#[derive(Debug, Clone)]
+pub struct Dopri45 {
+    pub relative_tolerance: f64,
+    /// The smallest step size before the integration is aborted.
+    pub min_step_size: f64,
+    /// The largest step size the integrator may take.
+    pub max_step_size: f64,
+    nominals: Vec<f64>,
+    /// The proposed size of the next step.
+    h: Option<f64>,
+    /// Derivatives at the start of the next step, if known from the last step.
+    first_same_as_last: Option<(f64, Vec<f64>)>,
+    /// Start time and size of the last accepted step.
+    t: f64,
+    h_last: f64,
+    x_old: Vec<f64>,
+    k: [Vec<f64>; 7],
+    x_stage: Vec<f64>,
+    x_new: Vec<f64>,
+}
+
+// Butcher tableau of the Dormand-Prince 5(4) pair.
+const DOPRI_C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+const DOPRI_A: [&[f64]; 7] = [
+    &[],
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+    ],
+    &[
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+    ],
+    &[
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+/// Coefficients of the dense output polynomial.
+const DOPRI_D: [f64; 7] = [
+    -12715105075.0 / 11282082432.0,
+    0.0,
+    87487479700.0 / 32700410799.0,
+    -10690763975.0 / 1880347072.0,
+    701980252875.0 / 199316789632.0,
+    -1453857185.0 / 822651844.0,
+    69997945.0 / 29380423.0,
+];
+/// Difference between the 5th and 4th order weights, used to estimate the error.
+const DOPRI_E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+impl Dopri45 {
+    pub fn new(relative_tolerance: f64) -> Self {
+        Self {
+            relative_tolerance,
+            min_step_size: 1e-12,
+            max_step_size: f64::INFINITY,
+            nominals: Vec::new(),
+            h: None,
+            first_same_as_last: None,
+            t: 0.0,
+            h_last: 0.0,
+            x_old: Vec::new(),
+            k: Default::default(),
+            x_stage: Vec::new(),
+            x_new: Vec::new(),
+        }
+    }
+
+    /// Create an integrator using the tolerance of the `DefaultExperiment`.
+    pub fn from_model_description(model_description: &FmiModelDescription) -> Self {
+        let tolerance = model_description
+            .default_experiment
+            .as_ref()
+            .map(|e| e.tolerance)
+            .filter(|tolerance| *tolerance > 0.0)
+            .unwrap_or(DEFAULT_TOLERANCE);
+        Self::new(tolerance)
+    }
+
+    /// Weighted RMS norm of `v` with respect to the error tolerances around `x`.
+    fn error_norm(&self, v: &[f64], x: &[f64], x_new: &[f64]) -> f64 {
+        weighted_rms_norm(v, x, x_new, self.relative_tolerance, &self.nominals)
+    }
+
+    /// Estimate a first step size from the magnitude of the states and their derivatives.
+    fn initial_step_size(&self, x: &[f64], dx: &[f64], h_max: f64) -> f64 {
+        let d0 = self.error_norm(x, x, x);
+        let d1 = self.error_norm(dx, x, x);
+        let h = if d0 < 1e-5 || d1 < 1e-5 {
+            1e-6
+        } else {
+            0.01 * d0 / d1
+        };
+        h.min(h_max).min(self.max_step_size)
+    }
+}
+
+impl Default for Dopri45 {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE)
+    }
+}
+
+impl Integrator for Dopri45 {
+    fn step(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &mut [f64],
+        h_max: f64,
+    ) -> Result<f64, SolverError> {
+        let n = x.len();
+        self.k.iter_mut().for_each(|k| k.resize(n, 0.0));
+        self.x_stage.resize(n, 0.0);
+        self.x_new.resize(n, 0.0);
+
+        match self.first_same_as_last.take() {
+            Some((t_last, dx)) if t_last == t => self.k[0] = dx,
+            _ => system.derivatives(t, x, &mut self.k[0])?,
+        }
+
+        let proposed = match self.h {
+            Some(h) => h,
+            None => self.initial_step_size(x, &self.k[0], h_max),
+        }
+        .min(self.max_step_size);
+        let mut h = proposed.min(h_max);
+        let mut rejected = false;
+
+        loop {
+            if h < self.min_step_size && h < h_max {
+                return Err(SolverError::StepSizeTooSmall {
+                    time: t,
+                    step_size: h,
+                });
+            }
+
+            for stage in 1..7 {
+                self.x_stage.copy_from_slice(x);
+                for (j, a) in DOPRI_A[stage].iter().enumerate() {
+                    for i in 0..n {
+                        self.x_stage[i] += h * a * self.k[j][i];
+                    }
+                }
+                system.derivatives(t + DOPRI_C[stage] * h, &self.x_stage, &mut self.k[stage])?;
+            }
+            // The last stage is evaluated at the 5th order solution.
+            self.x_new.copy_from_slice(&self.x_stage);
+
+            let error: Vec<f64> = (0..n)
+                .map(|i| h * (0..7).map(|j| DOPRI_E[j] * self.k[j][i]).sum::<f64>())
+                .collect();
+            let error_norm = self.error_norm(&error, x, &self.x_new);
+            if !error_norm.is_finite() {
+                // A non-finite derivative, which a smaller step may avoid.
+                h *= 0.2;
+                rejected = true;
+                continue;
+            }
+
+            let factor = if error_norm == 0.0 {
+                5.0
+            } else {
+                (0.9 * error_norm.powf(-0.2)).clamp(0.2, 5.0)
+            };
+
+            if error_norm <= 1.0 {
+                self.t = t;
+                self.h_last = h;
+                self.x_old = x.to_vec();
+                x.copy_from_slice(&self.x_new);
+                self.first_same_as_last = Some((t + h, self.k[6].clone()));
+                // Don't let a step that was shortened by `h_max` shrink the next one.
+                self.h = Some(if proposed > h_max && !rejected {
+                    proposed.max(h * factor)
+                } else {
+                    h * factor
+                });
+                return Ok(h);
+            }
+
+            h *= factor.min(1.0);
+            rejected = true;
+        }
+    }
+
+    fn interpolate(&self, t: f64, x: &mut [f64]) {
+        let theta = (t - self.t) / self.h_last;
+        let h = self.h_last;
+        let k = &self.k;
+
+        for i in 0..x.len() {
+            let dx = self.x_new[i] - self.x_old[i];
+            let r3 = h * k[0][i] - dx;
+            let r4 = dx - h * k[6][i] - r3;
+            let r5 = h * (0..7).map(|j| DOPRI_D[j] * k[j][i]).sum::<f64>();
+            x[i] = self.x_old[i]
+                + theta * (dx + (1.0 - theta) * (r3 + theta * (r4 + (1.0 - theta) * r5)));
+        }
+    }
+
+    fn set_nominals(&mut self, nominals: &[f64]) {
+        self.nominals = nominals.to_vec();
+    }
+
+    fn reset(&mut self) {
+        self.h = None;
+        self.first_same_as_last = None;
+    }
+}
+
+/// Approximate the row-major Jacobian of `system` with forward differences.
+fn finite_difference_jacobian<S: OdeSystem + ?Sized>(
+    system: &mut S,
+    t: f64,
+    x: &[f64],
+    dx: &[f64],
+    columns: &ColumnGroups,
+    jacobian: &mut [f64],
+) -> Result<(), FmuError> {
+    let n = x.len();
+    let mut x_perturbed = x.to_vec();
+    let mut dx_perturbed = vec![0.0; n];
+    jacobian.fill(0.0);
+
+    for group in &columns.groups {
+        for &j in group {
+            x_perturbed[j] += perturbation(x[j]);
+        }
+        system.derivatives(t, &x_perturbed, &mut dx_perturbed)?;
+
+        for &j in group {
+            let delta = x_perturbed[j] - x[j];
+            for &i in &columns.rows[j] {
+                jacobian[i * n + j] = (dx_perturbed[i] - dx[i]) / delta;
+            }
+            x_perturbed[j] = x[j];
+        }
+    }
+
+    Ok(())
+}
+
+/// Finite difference perturbation for a state with value `x`.
+fn perturbation(x: f64) -> f64 {
+    f64::EPSILON.sqrt() * x.abs().max(1.0)
+}
+
+/// Evaluate the state derivatives of a Model Exchange FMU.
+fn fmu_derivatives<C: Borrow<FmuLibrary>>(
+    instance: &FmuInstance<C>,
+    t: f64,
+    x: &[f64],
+    dx: &mut [f64],
+) -> Result<(), FmuError> {
+    instance.set_time(t)?;
+    instance.set_continuous_states(x)?;
+    dx.copy_from_slice(&instance.get_derivatives()?);
+    Ok(())
+}
+
+/// The right-hand side of a Model Exchange FMU.
+struct FmuOdeSystem<'fmu, C: Borrow<FmuLibrary>> {
+    instance: &'fmu FmuInstance<C>,
+    /// The states and their derivatives, if the FMU can compute directional
+    /// derivatives.
+    directional_derivative: Option<(Vec<&'fmu ScalarVariable>, Vec<&'fmu ScalarVariable>)>,
+    /// Sparsity of the Jacobian from the `ModelStructure`.
+    columns: ColumnGroups,
+}
+
+impl<'fmu, C: Borrow<FmuLibrary>> FmuOdeSystem<'fmu, C> {
+    fn new(instance: &'fmu FmuInstance<C>) -> Self {
+        let model_description = &instance.lib.borrow().model_description;
+        let n = model_description.continuous_state_count();
+
+        let (states, derivatives): (Vec<_>, Vec<_>) =
+            model_description.continuous_states().into_iter().unzip();
+        if states.len() != n {
+            return Self {
+                instance,
+                directional_derivative: None,
+                columns: ColumnGroups::dense(n, n),
+            };
+        }
+
+        let sparsity = jacobian::sparsity(model_description, &derivatives, &states);
+        Self {
+            instance,
+            columns: ColumnGroups::new(&sparsity, n),
+            directional_derivative: instance
+                .provides_directional_derivative()
+                .then_some((states, derivatives)),
+        }
+    }
+}
+
+impl<'fmu, C: Borrow<FmuLibrary>> OdeSystem for FmuOdeSystem<'fmu, C> {
+    fn derivatives(&mut self, t: f64, x: &[f64], dx: &mut [f64]) -> Result<(), FmuError> {
+        fmu_derivatives(self.instance, t, x, dx)
+    }
+
+    /// Use directional derivatives if the FMU provides them, and finite differences
+    /// otherwise. Either way, columns that are independent according to the
+    /// `ModelStructure` are evaluated together.
+    fn jacobian(
+        &mut self,
+        t: f64,
+        x: &[f64],
+        dx: &[f64],
+        jacobian: &mut [f64],
+    ) -> Result<(), FmuError> {
+        let instance = self.instance;
+        let Some((states, derivatives)) = &self.directional_derivative else {
+            return finite_difference_jacobian(
+                &mut |t, x: &[f64], dx: &mut [f64]| fmu_derivatives(instance, t, x, dx),
+                t,
+                x,
+                dx,
+                &self.columns,
+                jacobian,
+            );
+        };
+
+        instance.set_time(t)?;
+        instance.set_continuous_states(x)?;
+        self.columns.assemble(
+            |seed| {
+                instance
+                    .get_directional_derivative(derivatives, states, seed)
+                    .map(Checked::into_inner)
+            },
+            jacobian,
+        )
+    }
+}
+
+/// Simulate a Model Exchange instance from `start_time` to `stop_time`.
+///
+/// The instance must have been instantiated as [`fmi2ModelExchange`] and have just
+/// left initialization mode, i.e. it is in Event Mode. `observer` is called after
+/// the initial event update and after every accepted step or event.
+///
+/// Returns the time at which the simulation stopped, which is earlier than
+/// `stop_time` if the FMU requested to terminate the simulation.
+///
+/// [`fmi2ModelExchange`]: libfmi::fmi2Type::fmi2ModelExchange
+pub fn simulate<C, F>(
+    instance: &FmuInstance<C>,
+    integrator: &mut dyn Integrator,
+    start_time: fmi2Real,
+    stop_time: fmi2Real,
+    mut observer: F,
+) -> Result<fmi2Real, SolverError>
+where
+    C: Borrow<FmuLibrary>,
+    F: FnMut(fmi2Real, &FmuInstance<C>),
+{
+    let completed_integrator_step_needed = !instance
+        .lib
+        .borrow()
+        .model_description
+        .model_exchange
+        .as_ref()
+        .is_some_and(|me| me.completed_integrator_step_not_needed);
+
+    let mut t = start_time;
+
+    let mut event_info = event_update(instance)?;
+    if event_info.terminate_simulation {
+        observer(t, instance);
+        return Ok(t);
+    }
+    instance.enter_continuous_time_mode()?;
+
+    let mut x = instance.get_continuous_states()?.into_inner();
+    let mut indicators = instance.get_event_indicators()?.into_inner();
+    integrator.set_nominals(&instance.get_nominals_of_continuous_states()?);
+    integrator.reset();
+
+    observer(t, instance);
+
+    let mut system = FmuOdeSystem::new(instance);
+    while !time_reached(t, stop_time) {
+        let t_end = match event_info.next_event_time {
+            Some(next_event_time) => next_event_time.min(stop_time),
+            None => stop_time,
+        };
+
+        let t_start = t;
+        let h = if x.is_empty() {
+            t_end - t
+        } else {
+            integrator.step(&mut system, t, &mut x, t_end - t)?
+        };
+        t = if time_reached(t + h, t_end) {
+            t_end
+        } else {
+            t + h
+        };
+
+        instance.set_time(t)?;
+        instance.set_continuous_states(&x)?;
+
+        let new_indicators = instance.get_event_indicators()?.into_inner();
+        let state_event = zero_crossing(&indicators, &new_indicators);
+        if state_event {
+            t = locate_state_event(
+                &mut |t, x| {
+                    instance.set_time(t)?;
+                    instance.set_continuous_states(x)?;
+                    instance.get_event_indicators().map(Checked::into_inner)
+                },
+                integrator,
+                (t_start, &indicators),
+                (t, new_indicators),
+                &mut x,
+            )?;
+            instance.set_time(t)?;
+            instance.set_continuous_states(&x)?;
+            // The rest of the step is discarded, so the integrator can't continue from it.
+            integrator.reset();
+        } else {
+            indicators = new_indicators;
+        }
+
+        let step_event = if completed_integrator_step_needed {
+            let step = instance.completed_integrator_step(true)?;
+            if step.terminate_simulation {
+                observer(t, instance);
+                break;
+            }
+            step.enter_event_mode
+        } else {
+            false
+        };
+
+        let time_event = event_info
+            .next_event_time
+            .is_some_and(|next_event_time| time_reached(t, next_event_time));
+
+        if time_event || step_event || state_event {
+            instance.enter_event_mode()?;
+            event_info = event_update(instance)?;
+            if event_info.terminate_simulation {
+                observer(t, instance);
+                break;
+            }
+            instance.enter_continuous_time_mode()?;
+
+            if event_info.values_of_continuous_states_changed {
+                x = instance.get_continuous_states()?.into_inner();
+            }
+            // The derivatives may have changed even if the states didn't.
+            integrator.reset();
+            if event_info.nominals_of_continuous_states_changed {
+                integrator.set_nominals(&instance.get_nominals_of_continuous_states()?);
+            }
+            indicators = instance.get_event_indicators()?.into_inner();
+        }
+
+        observer(t, instance);
+    }
+
+    Ok(t)
+}
+
+/// Iterate `fmi2NewDiscreteStates()` until the discrete states have converged.
+///
+/// The "changed" flags are accumulated over all iterations.
+fn event_update<C: Borrow<FmuLibrary>>(instance: &FmuInstance<C>) -> Result<EventInfo, FmuError> {
+    let mut values_changed = false;
+    let mut nominals_changed = false;
+
+    loop {
+        let event_info = instance.new_discrete_states()?.into_inner();
+        values_changed |= event_info.values_of_continuous_states_changed;
+        nominals_changed |= event_info.nominals_of_continuous_states_changed;
+
+        if !event_info.new_discrete_states_needed || event_info.terminate_simulation {
+            return Ok(EventInfo {
+                values_of_continuous_states_changed: values_changed,
+                nominals_of_continuous_states_changed: nominals_changed,
+                ..event_info
+            });
+        }
+    }
+}
+
+/// Whether any event indicator changed its domain between `before` and `after`.
+///
+/// FMI defines the domains of an event indicator as `z > 0` and `z <= 0`.
+fn zero_crossing(before: &[f64], after: &[f64]) -> bool {
+    zip(before, after).any(|(before, after)| (*before > 0.0) != (*after > 0.0))
+}
+
+/// Evaluates the event indicators at the given time and states.
+type IndicatorFn<'a> = dyn FnMut(f64, &[f64]) -> Result<Vec<f64>, FmuError> + 'a;
+
+/// Locate the earliest zero crossing of the event indicators within the last step.
+///
+/// `left` and `right` are the times and event indicators at the start and end of
+/// the step. The crossing is located with the Illinois variant of regula falsi on
+/// the dense output of the integrator. Returns the time right after the crossing,
+/// with `x` set to the interpolated states at that time.
+fn locate_state_event(
+    indicators: &mut IndicatorFn,
+    integrator: &dyn Integrator,
+    (mut t_left, g_left): (f64, &[f64]),
+    (mut t_right, mut g_right): (f64, Vec<f64>),
+    x: &mut [f64],
+) -> Result<f64, SolverError> {
+    let tolerance = EVENT_TIME_TOLERANCE * t_right.abs().max(1.0);
+    let mut g_left = g_left.to_vec();
+    // Illinois scaling of the indicators at an endpoint that was retained repeatedly.
+    let mut scale_left = 1.0;
+    let mut scale_right = 1.0;
+    let mut last_moved_left = None;
+
+    while t_right - t_left > tolerance {
+        let t_secant = zip(&g_left, &g_right)
+            .filter(|(l, r)| (**l > 0.0) != (**r > 0.0))
+            .map(|(l, r)| {
+                let (l, r) = (l * scale_left, r * scale_right);
+                t_left + (t_right - t_left) * l / (l - r)
+            })
+            .fold(t_right, f64::min);
+        let t_mid = t_secant.clamp(t_left + 0.5 * tolerance, t_right - 0.5 * tolerance);
+
+        integrator.interpolate(t_mid, x);
+        let g_mid = indicators(t_mid, x)?;
+
+        let moved_left = zero_crossing(&g_left, &g_mid);
+        if moved_left {
+            t_right = t_mid;
+            g_right = g_mid;
+            scale_right = 1.0;
+            if last_moved_left == Some(true) {
+                scale_left *= 0.5;
+            }
+        } else {
+            t_left = t_mid;
+            g_left = g_mid;
+            scale_left = 1.0;
+            if last_moved_left == Some(false) {
+                scale_right *= 0.5;
+            }
+        }
+        last_moved_left = Some(moved_left);
+    }
+
+    integrator.interpolate(t_right, x);
+    Ok(t_right)
+}
+
+/// Root mean square of `v`, weighted by the tolerance at the states `x` and `x_new`.
+fn weighted_rms_norm(
+    v: &[f64],
+    x: &[f64],
+    x_new: &[f64],
+    relative_tolerance: f64,
+    nominals: &[f64],
+) -> f64 {
+    if v.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = (0..v.len())
+        .map(|i| {
+            let nominal = nominals.get(i).map_or(1.0, |n| n.abs());
+            let scale = relative_tolerance * (nominal + x[i].abs().max(x_new[i].abs()));
+            (v[i] / scale).powi(2)
+        })
+        .sum();
+    (sum / v.len() as f64).sqrt()
+}
+
+/// Whether `t` has reached `target`, allowing for floating point round-off.
+fn time_reached(t: f64, target: f64) -> bool {
+    t >= target - 1e-12 * target.abs().max(1.0)
+}
+
+/// `out = x + a * y`
+fn axpy(out: &mut [f64], x: &[f64], a: f64, y: &[f64]) {
+    for i in 0..out.len() {
+        out[i] = x[i] + a * y[i];
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SolverError {
+    #[error("FMU error during integration")]
+    Fmu(#[from] FmuError),
+    #[error("Step size {step_size:e} at t = {time} is below the minimum step size")]
+    StepSizeTooSmall { time: f64, step_size: f64 },
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integrate `dx/dt = -x` from `x(0) = 1` to `t = 1`.
+    fn decay(integrator: &mut dyn Integrator) -> f64 {
+        let mut system = |_t: f64, x: &[f64], dx: &mut [f64]| {
+            dx[0] = -x[0];
+            Ok(())
+        };
+
+        let mut t = 0.0;
+        let mut x = [1.0];
+        while !time_reached(t, 1.0) {
+            t += integrator.step(&mut system, t, &mut x, 1.0 - t).unwrap();
+        }
+        x[0]
+    }
+
+    #[test]
+    fn test_fixed_step_order() {
+        let exact = (-1.0f64).exp();
+
+        let euler_error = (decay(&mut Euler::new(0.01)) - exact).abs();
+        assert!(euler_error < 1e-2 && euler_error > 1e-4);
+
+        let rk4_error = (decay(&mut Rk4::new(0.01)) - exact).abs();
+        assert!(rk4_error < 1e-9);
+    }
+
+    #[test]
+    fn test_dense_output() {
+        let mut system = |_t: f64, x: &[f64], dx: &mut [f64]| {
+            dx[0] = -x[0];
+            Ok(())
+        };
+        let integrators: [(Box<dyn Integrator>, f64); 3] = [
+            (Box::new(Euler::new(0.1)), 1e-2),
+            (Box::new(Rk4::new(0.1)), 1e-5),
+            (Box::new(Dopri45::new(1e-8)), 1e-6),
+        ];
+
+        for (mut integrator, tolerance) in integrators {
+            let mut x = [1.0];
+            let h = integrator.step(&mut system, 0.0, &mut x, 0.1).unwrap();
+
+            let mut x_mid = [0.0];
+            integrator.interpolate(0.5 * h, &mut x_mid);
+            assert!((x_mid[0] - (-0.5 * h).exp()).abs() < tolerance);
+
+            integrator.interpolate(h, &mut x_mid);
+            assert!((x_mid[0] - x[0]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_locate_state_event() {
+        // A ball falling from 1m at 1 m/s, hitting the ground at t = 1.
+        let mut system = |_t: f64, _x: &[f64], dx: &mut [f64]| {
+            dx[0] = -1.0;
+            Ok(())
+        };
+        let mut indicators = |_t: f64, x: &[f64]| Ok(vec![x[0], 0.5 - x[0]]);
+
+        let mut integrator = Rk4::new(0.4);
+        let mut x = [0.2];
+        let h = integrator.step(&mut system, 0.8, &mut x, 1.0).unwrap();
+        assert_eq!(h, 0.4);
+
+        let g_left = indicators(0.8, &[0.2]).unwrap();
+        let g_right = indicators(1.2, &x).unwrap();
+        assert!(zero_crossing(&g_left, &g_right));
+
+        let t = locate_state_event(
+            &mut indicators,
+            &integrator,
+            (0.8, &g_left),
+            (1.2, g_right),
+            &mut x,
+        )
+        .unwrap();
+        assert!((t - 1.0).abs() < 10.0 * EVENT_TIME_TOLERANCE);
+        assert!(x[0] <= 0.0 && x[0].abs() < 10.0 * EVENT_TIME_TOLERANCE);
+    }
+
+    #[test]
+    fn test_sparse_finite_difference_jacobian() {
+        // A tridiagonal system only needs 3 evaluations for its Jacobian.
+        const N: usize = 6;
+        let mut evaluations = 0;
+        let mut system = |_t: f64, x: &[f64], dx: &mut [f64]| {
+            evaluations += 1;
+            for i in 0..N {
+                let left = if i > 0 { x[i - 1] } else { 0.0 };
+                let right = if i < N - 1 { x[i + 1] } else { 0.0 };
+                dx[i] = left - 2.0 * x[i] * x[i] + right;
+            }
+            Ok(())
+        };
+
+        let sparsity = (0..N)
+            .map(|i| (i.saturating_sub(1)..(i + 2).min(N)).collect())
+            .collect::<Vec<Vec<usize>>>();
+        let columns = ColumnGroups::new(&sparsity, N);
+
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mut dx = [0.0; N];
+        system.derivatives(0.0, &x, &mut dx).unwrap();
+        let mut jacobian = [0.0; N * N];
+        finite_difference_jacobian(&mut system, 0.0, &x, &dx, &columns, &mut jacobian).unwrap();
+        assert_eq!(evaluations, 4);
+
+        for i in 0..N {
+            for j in 0..N {
+                let exact = match i.abs_diff(j) {
+                    0 => -4.0 * x[i],
+                    1 => 1.0,
+                    _ => 0.0,
+                };
+                assert!((jacobian[i * N + j] - exact).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dopri45_tolerance() {
+        let exact = (-1.0f64).exp();
+
+        for tolerance in [1e-4, 1e-8] {
+            let error = (decay(&mut Dopri45::new(tolerance)) - exact).abs();
+            assert!(error < 10.0 * tolerance, "error {error} for {tolerance}");
+        }
+    }
+
+    #[test]
+    fn test_dopri45_non_finite_derivatives() {
+        // The derivatives are only defined for x < 1.1, which a large first step
+        // overshoots.
+        let mut system = |_t: f64, x: &[f64], dx: &mut [f64]| {
+            dx[0] = if x[0] < 1.1 { 1.0 } else { f64::NAN };
+            Ok(())
+        };
+        let mut integrator = Dopri45::new(1e-6);
+        integrator.h = Some(1.0);
+        let mut x = [1.0];
+        let h = integrator.step(&mut system, 0.0, &mut x, 1.0).unwrap();
+        assert!(h < 0.1 && x[0] < 1.1);
+
+        let mut system = |_t: f64, _x: &[f64], dx: &mut [f64]| {
+            dx[0] = f64::NAN;
+            Ok(())
+        };
+        assert!(matches!(
+            Dopri45::new(1e-6).step(&mut system, 0.0, &mut [1.0], 1.0),
+            Err(SolverError::StepSizeTooSmall { .. })
+        ));
+    }
+}
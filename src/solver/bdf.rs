@@ -0,0 +1,531 @@
+use super::{weighted_rms_norm, Integrator, OdeSystem, SolverError, DEFAULT_TOLERANCE};
+use crate::model_description::FmiModelDescription;
+use std::collections::VecDeque;
+
+/// The highest order of the backward differentiation formulas.
+const MAX_ORDER: usize = 5;
+/// Bounds on the factor by which the step size changes between steps.
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+/// Maximum number of Newton iterations per step attempt.
+const NEWTON_MAX_ITERATIONS: usize = 4;
+
+/// Variable order, variable step size backward differentiation formulas.
+///
+/// BDF methods are implicit, which makes them suitable for stiff models where
+/// explicit methods are limited to tiny steps to stay stable. The order is
+/// adapted between 1 and [`Bdf::max_order`] to take the largest steps that meet
+/// the tolerance.
+///
+/// The implicit equations of every step are solved with a Newton iteration using
+/// [`OdeSystem::jacobian()`]. The Jacobian is reused between steps and is only
+/// evaluated again when the iteration stops converging.
+#[derive(Debug, Clone)]
+pub struct Bdf {
+    pub relative_tolerance: f64,
+    /// The smallest step size before the integration is aborted.
+    pub min_step_size: f64,
+    /// The largest step size the integrator may take.
+    pub max_step_size: f64,
+    /// The highest order the integrator may use, between 1 and 5.
+    pub max_order: usize,
+    nominals: Vec<f64>,
+    /// The accepted solution points, most recent first.
+    history: VecDeque<(f64, Vec<f64>)>,
+    order: usize,
+    /// Number of steps taken since the order last changed.
+    steps_at_order: usize,
+    /// The proposed size of the next step.
+    h: Option<f64>,
+    /// The row-major Jacobian, if it has been evaluated.
+    jacobian: Option<Vec<f64>>,
+    /// Whether the Jacobian was evaluated at the start of the current step.
+    jacobian_current: bool,
+    /// LU factorization of the Newton iteration matrix for the given leading coefficient.
+    iteration_matrix: Option<(f64, Vec<f64>, Vec<usize>)>,
+}
+
+impl Bdf {
+    pub fn new(relative_tolerance: f64) -> Self {
+        Self {
+            relative_tolerance,
+            min_step_size: 1e-12,
+            max_step_size: f64::INFINITY,
+            max_order: MAX_ORDER,
+            nominals: Vec::new(),
+            history: VecDeque::new(),
+            order: 1,
+            steps_at_order: 0,
+            h: None,
+            jacobian: None,
+            jacobian_current: false,
+            iteration_matrix: None,
+        }
+    }
+
+    /// Create an integrator using the tolerance of the `DefaultExperiment`.
+    pub fn from_model_description(model_description: &FmiModelDescription) -> Self {
+        let tolerance = model_description
+            .default_experiment
+            .as_ref()
+            .map(|e| e.tolerance)
+            .filter(|tolerance| *tolerance > 0.0)
+            .unwrap_or(DEFAULT_TOLERANCE);
+        Self::new(tolerance)
+    }
+
+    fn norm(&self, v: &[f64], x: &[f64]) -> f64 {
+        weighted_rms_norm(v, x, x, self.relative_tolerance, &self.nominals)
+    }
+
+    /// Extrapolate the polynomial through the `points` most recent solution points to `t`.
+    fn predict(&self, points: usize, t: f64, x: &mut [f64]) {
+        lagrange(self.history.iter().take(points), t, x);
+    }
+
+    /// Error estimates of the new solution `x_new` for order `order`, from the
+    /// difference with the predictor of that order.
+    fn error_estimate(&self, order: usize, t_new: f64, x_new: &[f64]) -> f64 {
+        let mut predicted = vec![0.0; x_new.len()];
+        self.predict(order + 1, t_new, &mut predicted);
+        let difference = x_new
+            .iter()
+            .zip(&predicted)
+            .map(|(x, p)| x - p)
+            .collect::<Vec<_>>();
+        self.norm(&difference, x_new) / (order + 1) as f64
+    }
+
+    /// Solve the implicit BDF equation at `t_new` with a Newton iteration, starting
+    /// from the predicted `x_new`.
+    ///
+    /// Returns `false` if the iteration didn't converge.
+    fn newton(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t_new: f64,
+        alpha: &[f64],
+        x_new: &mut [f64],
+    ) -> Result<bool, SolverError> {
+        let n = x_new.len();
+
+        if !matches!(&self.iteration_matrix, Some((a, _, _)) if *a == alpha[0]) {
+            let mut matrix = self
+                .jacobian
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|j| -j)
+                .collect::<Vec<_>>();
+            for i in 0..n {
+                matrix[i * n + i] += alpha[0];
+            }
+            let Some(pivots) = lu_factor(&mut matrix, n) else {
+                return Ok(false);
+            };
+            self.iteration_matrix = Some((alpha[0], matrix, pivots));
+        }
+        let (_, lu, pivots) = self.iteration_matrix.as_ref().unwrap();
+
+        let tolerance = (10.0 * f64::EPSILON / self.relative_tolerance)
+            .max(self.relative_tolerance.sqrt().min(0.03));
+        let mut dx = vec![0.0; n];
+        let mut last_norm = None;
+
+        for _ in 0..NEWTON_MAX_ITERATIONS {
+            system.derivatives(t_new, x_new, &mut dx)?;
+
+            // The negated residual of `alpha[0] x_new + sum(alpha[j] x[j]) = f(t_new, x_new)`.
+            let mut delta = (0..n)
+                .map(|i| {
+                    let history: f64 = zip_history(&self.history, alpha, i);
+                    dx[i] - alpha[0] * x_new[i] - history
+                })
+                .collect::<Vec<_>>();
+            lu_solve(lu, pivots, &mut delta);
+
+            for (x, delta) in x_new.iter_mut().zip(&delta) {
+                *x += delta;
+            }
+
+            let norm = weighted_rms_norm(
+                &delta,
+                x_new,
+                x_new,
+                self.relative_tolerance,
+                &self.nominals,
+            );
+            if norm == 0.0 {
+                return Ok(true);
+            }
+            if let Some(last_norm) = last_norm {
+                let rate = norm / last_norm;
+                if rate >= 1.0 {
+                    return Ok(false);
+                }
+                if rate / (1.0 - rate) * norm < tolerance {
+                    return Ok(true);
+                }
+            }
+            last_norm = Some(norm);
+        }
+
+        Ok(false)
+    }
+
+    fn update_jacobian(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &[f64],
+    ) -> Result<(), SolverError> {
+        let n = x.len();
+        let mut dx = vec![0.0; n];
+        let mut jacobian = vec![0.0; n * n];
+        system.derivatives(t, x, &mut dx)?;
+        system.jacobian(t, x, &dx, &mut jacobian)?;
+
+        self.jacobian = Some(jacobian);
+        self.jacobian_current = true;
+        self.iteration_matrix = None;
+        Ok(())
+    }
+
+    /// Pick the order with the largest step size for the next step and return the
+    /// factor to change the step size by.
+    fn select_order(&mut self, error: f64, t_new: f64, x_new: &[f64]) -> f64 {
+        let factor = |error: f64, order: usize| {
+            if error == 0.0 {
+                MAX_FACTOR
+            } else {
+                0.9 * error.powf(-1.0 / (order + 1) as f64)
+            }
+        };
+
+        let mut candidates = vec![(factor(error, self.order), self.order)];
+        if self.order > 1 {
+            let order = self.order - 1;
+            let error = self.error_estimate(order, t_new, x_new);
+            candidates.push((factor(error, order), order));
+        }
+        if self.order < self.max_order.clamp(1, MAX_ORDER) && self.history.len() > self.order + 1 {
+            let order = self.order + 1;
+            let error = self.error_estimate(order, t_new, x_new);
+            candidates.push((factor(error, order), order));
+        }
+
+        let (factor, order) = candidates
+            .into_iter()
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        if order != self.order {
+            self.order = order;
+            self.steps_at_order = 0;
+        }
+        factor
+    }
+}
+
+impl Default for Bdf {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE)
+    }
+}
+
+impl Integrator for Bdf {
+    fn step(
+        &mut self,
+        system: &mut dyn OdeSystem,
+        t: f64,
+        x: &mut [f64],
+        h_max: f64,
+    ) -> Result<f64, SolverError> {
+        let n = x.len();
+
+        if self.history.front().map(|(t_last, _)| *t_last) != Some(t) {
+            self.reset();
+            self.history.push_front((t, x.to_vec()));
+        }
+        if self.jacobian.as_ref().map(Vec::len) != Some(n * n) {
+            self.update_jacobian(system, t, x)?;
+        }
+
+        // The first step has no history to extrapolate from, so it predicts with
+        // forward Euler instead.
+        let start_derivatives = if self.history.len() == 1 {
+            let mut dx = vec![0.0; n];
+            system.derivatives(t, x, &mut dx)?;
+            Some(dx)
+        } else {
+            None
+        };
+
+        let proposed = match (self.h, &start_derivatives) {
+            (Some(h), _) => h,
+            (None, Some(dx)) => {
+                let d0 = self.norm(x, x);
+                let d1 = self.norm(dx, x);
+                if d0 < 1e-5 || d1 < 1e-5 {
+                    1e-6
+                } else {
+                    0.01 * d0 / d1
+                }
+            }
+            (None, None) => h_max,
+        }
+        .min(self.max_step_size);
+        let mut h = proposed.min(h_max);
+        let mut rejected = false;
+        let mut x_new = vec![0.0; n];
+
+        loop {
+            if h < self.min_step_size && h < h_max {
+                return Err(SolverError::StepSizeTooSmall {
+                    time: t,
+                    step_size: h,
+                });
+            }
+
+            let t_new = t + h;
+            let order = self.order.min(self.history.len());
+
+            match &start_derivatives {
+                Some(dx) => {
+                    for i in 0..n {
+                        x_new[i] = x[i] + h * dx[i];
+                    }
+                }
+                None => self.predict(order + 1, t_new, &mut x_new),
+            }
+            let x_predicted = x_new.clone();
+
+            let alpha = bdf_coefficients(t_new, self.history.iter().take(order).map(|p| p.0));
+            if !self.newton(system, t_new, &alpha, &mut x_new)? {
+                if self.jacobian_current {
+                    h *= 0.5;
+                    rejected = true;
+                } else {
+                    self.update_jacobian(system, t, x)?;
+                }
+                continue;
+            }
+
+            let difference = x_new
+                .iter()
+                .zip(&x_predicted)
+                .map(|(x, p)| x - p)
+                .collect::<Vec<_>>();
+            let error = self.norm(&difference, &x_new) / (order + 1) as f64;
+
+            if error > 1.0 {
+                h *= (0.9 * error.powf(-1.0 / (order + 1) as f64)).max(MIN_FACTOR);
+                rejected = true;
+                continue;
+            }
+
+            self.steps_at_order += 1;
+            let factor = if self.steps_at_order > self.order && start_derivatives.is_none() {
+                self.select_order(error, t_new, &x_new)
+                    .clamp(MIN_FACTOR, MAX_FACTOR)
+            } else {
+                1.0
+            };
+
+            // Don't let a step that was shortened by `h_max` shrink the next one.
+            self.h = Some(if proposed > h_max && !rejected {
+                proposed.max(h * factor)
+            } else {
+                h * factor
+            });
+
+            self.history.push_front((t_new, x_new.clone()));
+            self.history.truncate(MAX_ORDER + 2);
+            self.jacobian_current = false;
+
+            x.copy_from_slice(&x_new);
+            return Ok(h);
+        }
+    }
+
+    /// Interpolate with the polynomial through the points of the last step's formula.
+    fn interpolate(&self, t: f64, x: &mut [f64]) {
+        self.predict(self.order.min(self.history.len() - 1) + 1, t, x);
+    }
+
+    fn set_nominals(&mut self, nominals: &[f64]) {
+        self.nominals = nominals.to_vec();
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.order = 1;
+        self.steps_at_order = 0;
+        self.h = None;
+        self.jacobian_current = false;
+    }
+}
+
+/// `sum(alpha[j] * x[j - 1][i])` over the history points of the BDF formula.
+fn zip_history(history: &VecDeque<(f64, Vec<f64>)>, alpha: &[f64], i: usize) -> f64 {
+    alpha[1..]
+        .iter()
+        .zip(history)
+        .map(|(alpha, (_, x))| alpha * x[i])
+        .sum()
+}
+
+/// Coefficients of the variable step BDF formula.
+///
+/// These are the derivatives at `t_new` of the Lagrange basis polynomials through
+/// `t_new` and the previous solution `times`, so that the derivative of the
+/// interpolating polynomial is `alpha[0] x_new + sum(alpha[j] x[j - 1])`.
+fn bdf_coefficients(t_new: f64, times: impl Iterator<Item = f64>) -> Vec<f64> {
+    let nodes = std::iter::once(t_new).chain(times).collect::<Vec<_>>();
+    let mut alpha = vec![0.0; nodes.len()];
+
+    alpha[0] = nodes[1..].iter().map(|t| 1.0 / (t_new - t)).sum();
+    for j in 1..nodes.len() {
+        let numerator: f64 = (1..nodes.len())
+            .filter(|&m| m != j)
+            .map(|m| t_new - nodes[m])
+            .product();
+        let denominator: f64 = (0..nodes.len())
+            .filter(|&m| m != j)
+            .map(|m| nodes[j] - nodes[m])
+            .product();
+        alpha[j] = numerator / denominator;
+    }
+
+    alpha
+}
+
+/// Evaluate the polynomial through `points` at `t`.
+fn lagrange<'a>(points: impl Iterator<Item = &'a (f64, Vec<f64>)> + Clone, t: f64, x: &mut [f64]) {
+    x.fill(0.0);
+    for (j, (t_j, x_j)) in points.clone().enumerate() {
+        let basis: f64 = points
+            .clone()
+            .enumerate()
+            .filter(|(m, _)| *m != j)
+            .map(|(_, (t_m, _))| (t - t_m) / (t_j - t_m))
+            .product();
+        for (x, x_j) in x.iter_mut().zip(x_j) {
+            *x += basis * x_j;
+        }
+    }
+}
+
+/// LU factorization with partial pivoting of the row-major `n` by `n` matrix `a`.
+///
+/// Returns the row permutation, or `None` if the matrix is singular.
+fn lu_factor(a: &mut [f64], n: usize) -> Option<Vec<usize>> {
+    let mut pivots = (0..n).collect::<Vec<_>>();
+
+    for k in 0..n {
+        let p = (k..n).max_by(|&i, &j| a[i * n + k].abs().total_cmp(&a[j * n + k].abs()))?;
+        if a[p * n + k] == 0.0 {
+            return None;
+        }
+        if p != k {
+            for j in 0..n {
+                a.swap(k * n + j, p * n + j);
+            }
+            pivots.swap(k, p);
+        }
+
+        for i in k + 1..n {
+            a[i * n + k] /= a[k * n + k];
+            for j in k + 1..n {
+                a[i * n + j] -= a[i * n + k] * a[k * n + j];
+            }
+        }
+    }
+
+    Some(pivots)
+}
+
+/// Solve `A x = b` in place, given the factorization from [`lu_factor()`].
+fn lu_solve(lu: &[f64], pivots: &[usize], b: &mut [f64]) {
+    let n = pivots.len();
+    let mut x = pivots.iter().map(|&p| b[p]).collect::<Vec<_>>();
+
+    for i in 0..n {
+        for j in 0..i {
+            x[i] -= lu[i * n + j] * x[j];
+        }
+    }
+    for i in (0..n).rev() {
+        for j in i + 1..n {
+            x[i] -= lu[i * n + j] * x[j];
+        }
+        x[i] /= lu[i * n + i];
+    }
+
+    b.copy_from_slice(&x);
+}
+
+// test module
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lu_solve() {
+        let mut a = [0.0, 2.0, 1.0, 1.0, 1.0, 0.0, 3.0, 0.0, 1.0];
+        let pivots = lu_factor(&mut a, 3).unwrap();
+        let mut b = [5.0, 3.0, 4.0];
+        lu_solve(&a, &pivots, &mut b);
+        assert!(b
+            .iter()
+            .zip([1.0, 2.0, 1.0])
+            .all(|(x, exact)| (x - exact).abs() < 1e-12));
+
+        assert!(lu_factor(&mut [1.0, 2.0, 2.0, 4.0], 2).is_none());
+    }
+
+    #[test]
+    fn test_bdf_coefficients() {
+        // The constant step BDF2 formula is (3 x_new - 4 x[0] + x[1]) / (2 h).
+        let alpha = bdf_coefficients(0.2, [0.1, 0.0].into_iter());
+        for (alpha, exact) in alpha.iter().zip([15.0, -20.0, 5.0]) {
+            assert!((alpha - exact).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_stiff() {
+        // x' = -1000 (x - cos(t)) quickly settles onto a slow solution, which explicit
+        // methods can only follow with steps below their stability limit of ~1e-3.
+        let mut evaluations = 0;
+        let mut system = |t: f64, x: &[f64], dx: &mut [f64]| {
+            evaluations += 1;
+            dx[0] = -1000.0 * (x[0] - t.cos());
+            Ok(())
+        };
+        let exact =
+            |t: f64| (1e6 * t.cos() + 1e3 * t.sin() - 1e6 * (-1000.0 * t).exp()) / (1e6 + 1.0);
+
+        let mut bdf = Bdf::new(1e-6);
+        let mut t = 0.0;
+        let mut x = [0.0];
+        let mut steps = 0;
+        while t < 2.0 {
+            t += bdf.step(&mut system, t, &mut x, 2.0 - t).unwrap();
+            steps += 1;
+
+            let mut x_mid = [0.0];
+            bdf.interpolate(t, &mut x_mid);
+            assert_eq!(x_mid, x);
+        }
+
+        assert!(
+            (x[0] - exact(2.0)).abs() < 1e-5,
+            "{} != {}",
+            x[0],
+            exact(2.0)
+        );
+        assert!(steps < 200, "took {steps} steps");
+        assert!(bdf.order > 1);
+        assert!(evaluations < 1000, "took {evaluations} evaluations");
+    }
+}
@@ -32,7 +32,13 @@ fn test_bouncing_ball() {
         // Exit initialization mode
         fmu_cs.exit_initialization_mode().unwrap();
 
-        fmu_cs.do_step(0.0, 1.0, true).unwrap();
+        assert_eq!(
+            fmu_cs.do_step(0.0, 1.0, true).unwrap(),
+            StepOutcome::Completed {
+                status: libfmi::fmi2Status::fmi2OK
+            }
+        );
+        assert!(!*fmu_cs.terminated().unwrap());
 
         let outputs = fmu_cs.get_reals(&[&signals["h_m"]]).unwrap();
         println!("{}", outputs_to_string(&outputs));
@@ -92,7 +98,8 @@ fn test_bouncing_ball_with_snapshot() {
             .get_set_state_capability()
             .unwrap()
             .get_state()
-            .unwrap();
+            .unwrap()
+            .into_inner();
 
         // In real life you would normally save the snapshot in a file or in a database
         // but here we just keep it in memory for simplicity of the test.
@@ -182,7 +189,8 @@ fn test_bouncing_ball_with_snapshot_reinit() {
             .get_set_state_capability()
             .unwrap()
             .get_state()
-            .unwrap();
+            .unwrap()
+            .into_inner();
         serialized_state = fmu_cs
             .serialize_state_capability()
             .unwrap()
@@ -225,7 +233,8 @@ fn test_bouncing_ball_with_snapshot_reinit() {
             .serialize_state_capability()
             .unwrap()
             .deserialize_state(&serialized_state)
-            .unwrap();
+            .unwrap()
+            .into_inner();
         fmu_cs
             .get_set_state_capability()
             .unwrap()
@@ -245,6 +254,108 @@ fn test_bouncing_ball_with_snapshot_reinit() {
     }
 }
 
+// Output derivatives for extrapolation between communication points
+#[test]
+fn test_bouncing_ball_output_derivatives() {
+    let fmu = Fmu::unpack(Path::new("./tests/fmu/bouncing_ball.fmu"))
+        .unwrap()
+        .load(fmi2Type::fmi2CoSimulation)
+        .unwrap();
+
+    let signals = fmu.variables();
+
+    {
+        let fmu_cs = FmuInstance::instantiate(&fmu, true).unwrap();
+
+        fmu_cs.setup_experiment(0.0, None, None).unwrap();
+        fmu_cs
+            .set_reals(&HashMap::from([(&signals["h_start"], 10.0)]))
+            .unwrap();
+        fmu_cs.enter_initialization_mode().unwrap();
+        fmu_cs.exit_initialization_mode().unwrap();
+
+        fmu_cs.do_step(0.0, 0.5, true).unwrap();
+
+        // The derivative of the height is the velocity.
+        let velocity = fmu_cs.get_reals(&[&signals["v_mps"]]).unwrap();
+        let derivatives = fmu_cs
+            .get_real_output_derivatives(1, &[&signals["h_m"]])
+            .unwrap();
+        assert!(about_right(
+            derivatives[&signals["h_m"]],
+            velocity[&signals["v_mps"]]
+        ));
+
+        assert!(matches!(
+            fmu_cs.get_real_output_derivatives(2, &[&signals["h_m"]]),
+            Err(FmuError::OutputDerivativeOrder { order: 2, max: 1 })
+        ));
+    }
+}
+
+#[test]
+fn test_bouncing_ball_reset() {
+    let fmu = Fmu::unpack(Path::new("./tests/fmu/bouncing_ball.fmu"))
+        .unwrap()
+        .load(fmi2Type::fmi2CoSimulation)
+        .unwrap();
+
+    let signals = fmu.variables();
+    let fmu_cs = FmuInstance::instantiate(&fmu, true).unwrap();
+
+    let mut heights = Vec::new();
+    for _ in 0..2 {
+        fmu_cs.setup_experiment(0.0, None, None).unwrap();
+        fmu_cs.enter_initialization_mode().unwrap();
+        fmu_cs.exit_initialization_mode().unwrap();
+
+        fmu_cs.do_step(0.0, 0.5, true).unwrap();
+        let outputs = fmu_cs.get_reals(&[&signals["h_m"]]).unwrap();
+        heights.push(outputs[&signals["h_m"]]);
+
+        fmu_cs.terminate().unwrap();
+        fmu_cs.reset().unwrap();
+    }
+
+    // The second run starts from the initial state again.
+    assert_eq!(heights[0], heights[1]);
+}
+
+#[test]
+fn test_bouncing_ball_state_machine() {
+    let fmu = Fmu::unpack(Path::new("./tests/fmu/bouncing_ball.fmu"))
+        .unwrap()
+        .load(fmi2Type::fmi2CoSimulation)
+        .unwrap();
+
+    let signals = fmu.variables();
+    let fmu_cs = FmuInstance::instantiate(&fmu, true).unwrap();
+    assert_eq!(fmu_cs.state(), InstanceState::Instantiated);
+
+    assert!(matches!(
+        fmu_cs.do_step(0.0, 1.0, true),
+        Err(FmuError::IllegalCall {
+            function: "fmi2DoStep",
+            state: InstanceState::Instantiated,
+        })
+    ));
+
+    fmu_cs.setup_experiment(0.0, None, None).unwrap();
+    fmu_cs.enter_initialization_mode().unwrap();
+    fmu_cs.exit_initialization_mode().unwrap();
+    assert_eq!(fmu_cs.state(), InstanceState::SlaveInitialized);
+
+    // h_start is a fixed parameter.
+    assert!(matches!(
+        fmu_cs.set_reals(&HashMap::from([(&signals["h_start"], 5.0)])),
+        Err(FmuError::IllegalSet { .. })
+    ));
+
+    fmu_cs.do_step(0.0, 1.0, true).unwrap();
+    fmu_cs.terminate().unwrap();
+    assert_eq!(fmu_cs.state(), InstanceState::Terminated);
+}
+
 #[test]
 fn test_point_mass() {
     let fmu = Fmu::unpack(Path::new("./tests/fmu/point_mass_pendulum.fmu"))
@@ -442,12 +553,12 @@ fn about_right(a: f64, b: f64) -> bool {
     }
 
     let diff = (a - b).abs();
-    let norm = f64::min(a.abs() + b.abs(), std::f64::MAX);
+    let norm = f64::min(a.abs() + b.abs(), f64::MAX);
     if diff < EPSILON * norm {
-        return true;
+        true
     } else {
         eprintln!("{} ~!= {}", a, b);
-        return false;
+        false
     }
 }
 